@@ -0,0 +1,301 @@
+use bevy::animation::Keyframes;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Seconds of the cross-fade between two animations of the AnimationGraph
+pub const GRAPH_BLEND: f32 = 0.2;
+/// Seconds of the cross-fade from the previous body of Skelly to the new one
+pub const MODEL_SWAP_BLEND: f32 = 0.3;
+
+/// Clip and time of one side of a cross-fade
+#[derive(Clone, Debug)]
+pub struct ClipTime {
+    pub handle: Handle<AnimationClip>,
+    pub elapsed: f32,
+    pub repeat: bool,
+}
+
+impl ClipTime {
+    /// Time sampled in a clip of this duration, like the AnimationPlayer does
+    fn time(&self, duration: f32) -> f32 {
+        if self.repeat && duration > 0.0 {
+            self.elapsed % duration
+        } else {
+            self.elapsed.min(duration)
+        }
+    }
+}
+
+/// Local transforms of the bones of a scene, by name
+/// Taken before a scene is despawned, so that the next one fades from it
+#[derive(Component, Clone, Debug, Default)]
+pub struct PoseSnapshot(HashMap<Name, Transform>);
+
+impl PoseSnapshot {
+    /// Every named descendant of `root`
+    pub fn take(
+        root: Entity,
+        query_children: &Query<&Children>,
+        query_bones: &Query<(&Name, &Transform)>,
+    ) -> Self {
+        let mut snapshot = PoseSnapshot::default();
+        let mut stack = vec![root];
+        while let Some(entity) = stack.pop() {
+            if let Ok((name, transform)) = query_bones.get(entity) {
+                snapshot.0.insert(name.clone(), *transform);
+            }
+            if let Ok(children) = query_children.get(entity) {
+                stack.extend(children.iter());
+            }
+        }
+        snapshot
+    }
+}
+
+#[derive(Debug)]
+pub enum FadeSource {
+    /// the outgoing clip keeps playing during the fade
+    Clip(ClipTime),
+    /// the pose of a despawned scene, matched by bone name
+    Pose(PoseSnapshot),
+}
+
+/// Component of an AnimationPlayer fading to a new clip
+/// The player is paused during the fade, cross_fade samples both sides and drives the bones,
+/// then plays the new clip from where the fade left it
+#[derive(Component, Debug)]
+pub struct CrossFade {
+    pub from: FadeSource,
+    pub to: ClipTime,
+    pub duration: f32,
+    elapsed: f32,
+}
+
+impl CrossFade {
+    pub fn new(from: FadeSource, to: Handle<AnimationClip>, repeat: bool, duration: f32) -> Self {
+        Self {
+            from,
+            to: ClipTime {
+                handle: to,
+                elapsed: 0.0,
+                repeat,
+            },
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    /// 0 at the start of the fade, 1 once it is over
+    fn weight(&self) -> f32 {
+        if self.duration > 0.0 {
+            (self.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Shortest rotation, linear translation and scale
+fn blend_transforms(from: &Transform, to: &Transform, weight: f32) -> Transform {
+    let mut rotation = to.rotation;
+    if from.rotation.dot(rotation) < 0.0 {
+        rotation = -rotation;
+    }
+    Transform {
+        translation: from.translation.lerp(to.translation, weight),
+        rotation: from
+            .rotation
+            .normalize()
+            .slerp(rotation.normalize(), weight),
+        scale: from.scale.lerp(to.scale, weight),
+    }
+}
+
+/// Bone at the end of `path`, the first name is the root itself (see bevy's animation_player)
+fn find_bone(
+    root: Entity,
+    path: &[Name],
+    query_children: &Query<&Children>,
+    query_names: &Query<&Name>,
+) -> Option<Entity> {
+    let mut entity = root;
+    for part in path.iter().skip(1) {
+        entity = query_children
+            .get(entity)
+            .ok()?
+            .iter()
+            .find(|child| query_names.get(**child).map_or(false, |name| name == part))
+            .copied()?;
+    }
+    Some(entity)
+}
+
+/// Pose of the bones animated by `clip` at `time`,
+/// the channels the clip does not animate keep their current value
+fn sample_clip(
+    clip: &AnimationClip,
+    time: f32,
+    root: Entity,
+    query_children: &Query<&Children>,
+    query_names: &Query<&Name>,
+    query_transforms: &Query<&mut Transform>,
+) -> HashMap<Entity, Transform> {
+    let mut pose = HashMap::default();
+    for (path, curves) in clip.curves() {
+        let bone = match find_bone(root, &path.parts, query_children, query_names) {
+            Some(bone) => bone,
+            None => continue,
+        };
+        let mut transform = match query_transforms.get(bone) {
+            Ok(transform) => *transform,
+            Err(_) => continue,
+        };
+
+        for curve in curves {
+            let timestamps = &curve.keyframe_timestamps;
+            if timestamps.is_empty() {
+                continue;
+            }
+            // before the first keyframe or after the last one, the curve holds it
+            let (step, lerp) = match timestamps.iter().rposition(|timestamp| *timestamp <= time) {
+                Some(step) if step + 1 < timestamps.len() => (
+                    step,
+                    (time - timestamps[step]) / (timestamps[step + 1] - timestamps[step]),
+                ),
+                Some(step) => (step, 0.0),
+                None => (0, 0.0),
+            };
+            let next = (step + 1).min(timestamps.len() - 1);
+
+            match &curve.keyframes {
+                Keyframes::Rotation(keyframes) => {
+                    let mut end = keyframes[next];
+                    if end.dot(keyframes[step]) < 0.0 {
+                        end = -end;
+                    }
+                    transform.rotation = keyframes[step].normalize().slerp(end.normalize(), lerp);
+                }
+                Keyframes::Translation(keyframes) => {
+                    transform.translation = keyframes[step].lerp(keyframes[next], lerp);
+                }
+                Keyframes::Scale(keyframes) => {
+                    transform.scale = keyframes[step].lerp(keyframes[next], lerp);
+                }
+            }
+        }
+        pose.insert(bone, transform);
+    }
+    pose
+}
+
+/// Drives the bones of the players fading from a clip, or a pose, to a new clip
+/// Once the fade is over, the AnimationPlayer plays the new clip again
+pub(crate) fn cross_fade(
+    mut commands: Commands,
+    time: Res<Time>,
+    clips: Res<Assets<AnimationClip>>,
+    mut query_fades: Query<(Entity, &mut CrossFade, &mut AnimationPlayer)>,
+    query_children: Query<&Children>,
+    query_names: Query<&Name>,
+    mut query_transforms: Query<&mut Transform>,
+) {
+    for (entity, mut fade, mut player) in query_fades.iter_mut() {
+        let delta = time.delta_seconds();
+        fade.elapsed += delta;
+        fade.to.elapsed += delta;
+        if let FadeSource::Clip(from) = &mut fade.from {
+            from.elapsed += delta;
+        }
+
+        // without its clip, the fade is over at once
+        let to_pose = match clips.get(&fade.to.handle) {
+            Some(clip) => sample_clip(
+                clip,
+                fade.to.time(clip.duration()),
+                entity,
+                &query_children,
+                &query_names,
+                &query_transforms,
+            ),
+            None => {
+                fade.elapsed = fade.duration;
+                HashMap::default()
+            }
+        };
+        let from_pose = match &fade.from {
+            FadeSource::Clip(from) => {
+                clips
+                    .get(&from.handle)
+                    .map_or_else(HashMap::default, |clip| {
+                        sample_clip(
+                            clip,
+                            from.time(clip.duration()),
+                            entity,
+                            &query_children,
+                            &query_names,
+                            &query_transforms,
+                        )
+                    })
+            }
+            FadeSource::Pose(snapshot) => to_pose
+                .keys()
+                .filter_map(|bone| {
+                    let name = query_names.get(*bone).ok()?;
+                    snapshot.0.get(name).map(|transform| (*bone, *transform))
+                })
+                .collect(),
+        };
+
+        let weight = fade.weight();
+        for (bone, to) in to_pose.iter() {
+            if let Ok(mut transform) = query_transforms.get_mut(*bone) {
+                let current = *transform;
+                *transform = blend_transforms(from_pose.get(bone).unwrap_or(&current), to, weight);
+            }
+        }
+
+        if weight >= 1.0 {
+            commands.entity(entity).remove::<CrossFade>();
+            player.play(fade.to.handle.clone_weak());
+            if fade.to.repeat {
+                player.repeat();
+            }
+            player.set_elapsed(fade.to.elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend_transforms() {
+        let from = Transform::from_xyz(0.0, 0.0, 0.0);
+        let to = Transform {
+            translation: Vec3::new(2.0, 0.0, 0.0),
+            // same rotation as `from`, on the other side of the sphere
+            rotation: -Quat::IDENTITY,
+            scale: Vec3::ONE * 3.0,
+        };
+
+        assert_eq!(from, blend_transforms(&from, &to, 0.0));
+        let half = blend_transforms(&from, &to, 0.5);
+        assert_eq!(Vec3::new(1.0, 0.0, 0.0), half.translation);
+        assert_eq!(Vec3::ONE * 2.0, half.scale);
+        assert!(half.rotation.abs_diff_eq(Quat::IDENTITY, 1e-5));
+    }
+
+    #[test]
+    fn test_clip_time() {
+        let mut clip = ClipTime {
+            handle: Handle::default(),
+            elapsed: 2.5,
+            repeat: true,
+        };
+        assert_eq!(0.5, clip.time(1.0));
+
+        clip.repeat = false;
+        assert_eq!(1.0, clip.time(1.0));
+    }
+}
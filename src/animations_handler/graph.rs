@@ -0,0 +1,174 @@
+use crate::combat::{Attacking, Dead};
+use crate::creatures::ground::Airborne;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Gameplay values read by the transitions of the AnimationGraph
+/// `speed` and `running` are set by the controls or the AI,
+/// `grounded`, `attacking` and `dead` by update_animation_parameters
+#[derive(Component, Clone, Debug)]
+pub struct AnimationParameters {
+    /// horizontal speed the creature is asked to move at
+    pub speed: f32,
+    pub running: bool,
+    pub grounded: bool,
+    pub attacking: bool,
+    pub dead: bool,
+}
+
+impl Default for AnimationParameters {
+    fn default() -> Self {
+        Self {
+            speed: 0.0,
+            running: false,
+            grounded: true,
+            attacking: false,
+            dead: false,
+        }
+    }
+}
+
+/// Condition of a transition, checked every frame
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Condition {
+    /// the clip of the state played to its end
+    Finished,
+    Still,
+    Walking,
+    Running,
+    Falling,
+    Grounded,
+    Attacking,
+    /// the clip of the state played to its end and the creature is dead
+    Killed,
+}
+
+impl Condition {
+    fn is_met(self, parameters: &AnimationParameters, finished: bool) -> bool {
+        let moving = parameters.speed > 0.0;
+        match self {
+            Condition::Finished => finished,
+            Condition::Still => !moving,
+            Condition::Walking => moving && !parameters.running,
+            Condition::Running => moving && parameters.running,
+            Condition::Falling => !parameters.grounded,
+            Condition::Grounded => parameters.grounded,
+            Condition::Attacking => parameters.attacking,
+            Condition::Killed => finished && parameters.dead,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AnimationState {
+    repeat: bool,
+    /// checked in order, the first one met is taken
+    transitions: Vec<(Condition, usize)>,
+}
+
+/// States of the animations of a creature, keyed by animation index, and their transitions
+/// Evaluated by the AnimationHandler for every Creature
+/// A state without transitions is kept until a ChangeAnimation event (Hit, Die, Spawn) leaves it
+#[derive(Component, Clone, Debug, Default)]
+pub struct AnimationGraph {
+    states: HashMap<usize, AnimationState>,
+}
+
+impl AnimationGraph {
+    pub fn with_state(
+        mut self,
+        index: usize,
+        repeat: bool,
+        transitions: &[(Condition, usize)],
+    ) -> Self {
+        self.states.insert(
+            index,
+            AnimationState {
+                repeat,
+                transitions: transitions.to_vec(),
+            },
+        );
+        self
+    }
+
+    pub fn repeat(&self, index: usize) -> bool {
+        self.states.get(&index).map_or(false, |state| state.repeat)
+    }
+
+    /// Animation following `current`, and whether it repeats
+    pub fn next(
+        &self,
+        current: usize,
+        parameters: &AnimationParameters,
+        finished: bool,
+    ) -> Option<(usize, bool)> {
+        self.states
+            .get(&current)?
+            .transitions
+            .iter()
+            .find(|(condition, _)| condition.is_met(parameters, finished))
+            .map(|(_, index)| (*index, self.repeat(*index)))
+    }
+}
+
+/// Reads the ground, the attacks and the deaths of the creatures
+pub(crate) fn update_animation_parameters(
+    mut query_creatures: Query<(
+        &mut AnimationParameters,
+        Option<&Airborne>,
+        Option<&Attacking>,
+        Option<&Dead>,
+    )>,
+) {
+    for (mut parameters, airborne, attacking, dead) in query_creatures.iter_mut() {
+        parameters.grounded = !airborne.map_or(false, Airborne::is_falling);
+        parameters.attacking = attacking.is_some();
+        parameters.dead = dead.is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_transition_met() {
+        let graph = AnimationGraph::default()
+            .with_state(0, false, &[(Condition::Finished, 1)])
+            .with_state(1, true, &[(Condition::Falling, 3), (Condition::Running, 2)])
+            .with_state(2, true, &[(Condition::Still, 1)]);
+        let mut parameters = AnimationParameters::default();
+
+        assert_eq!(None, graph.next(0, &parameters, false));
+        assert_eq!(Some((1, true)), graph.next(0, &parameters, true));
+        assert_eq!(None, graph.next(1, &parameters, true));
+
+        parameters.speed = 6.0;
+        parameters.running = true;
+        assert_eq!(Some((2, true)), graph.next(1, &parameters, false));
+
+        // falling comes first, the target has no state: it does not repeat
+        parameters.grounded = false;
+        assert_eq!(Some((3, false)), graph.next(1, &parameters, false));
+        assert_eq!(None, graph.next(3, &parameters, true));
+    }
+
+    #[test]
+    fn test_killed_after_finished() {
+        let graph = AnimationGraph::default().with_state(
+            0,
+            false,
+            &[(Condition::Killed, 2), (Condition::Finished, 1)],
+        );
+        let mut parameters = AnimationParameters {
+            dead: true,
+            ..default()
+        };
+
+        assert_eq!(None, graph.next(0, &parameters, false));
+        assert_eq!(Some((2, false)), graph.next(0, &parameters, true));
+
+        parameters.dead = false;
+        assert_eq!(Some((1, false)), graph.next(0, &parameters, true));
+    }
+}
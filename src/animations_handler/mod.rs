@@ -1,10 +1,24 @@
 use crate::creatures::{Creature, TypeCreature};
+use bevy::animation::animation_player;
+use bevy::asset::LoadState;
 use bevy::prelude::*;
-use bevy::utils::HashMap;
+use bevy::transform::TransformSystem;
+use bevy::utils::{HashMap, HashSet};
 use bevy_inspector_egui::Inspectable;
+use blend::{
+    cross_fade, ClipTime, CrossFade, FadeSource, PoseSnapshot, GRAPH_BLEND, MODEL_SWAP_BLEND,
+};
+use graph::{update_animation_parameters, AnimationGraph, AnimationParameters};
+use serde::Deserialize;
 use std::borrow::BorrowMut;
 use std::time::Duration;
 
+pub(crate) mod blend;
+pub(crate) mod graph;
+
+/// Seconds given to a clip which will never be loaded, so that its creature does not wait forever
+const MISSING_CLIP_DURATION: f32 = 1.0;
+
 pub struct AnimationHandler;
 impl Plugin for AnimationHandler {
     fn build(&self, app: &mut App) {
@@ -13,14 +27,34 @@ impl Plugin for AnimationHandler {
             .add_event::<ChangeAnimation>()
             .add_event::<AddAnimation>()
             .add_event::<RemoveAnimation>()
+            .add_event::<AnimationEvent>()
             .add_system(link_animations)
             .add_system(start_animation.after(link_animations))
+            .add_system(update_clip_durations)
+            .add_system(give_up_missing_clips)
             .add_system_to_stage(CoreStage::PostUpdate, add_animation)
             .add_system_to_stage(CoreStage::PostUpdate, remove_animation)
-            .add_system_to_stage(CoreStage::PostUpdate, update_animation.after(add_animation))
-            .add_system_to_stage(CoreStage::PostUpdate, checker_animation_duration.after(update_animation))
-            //.add_system(inspect_animation_clip)
-        ;
+            // the player is paused before bevy samples it, cross_fade drives the bones after it
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_animation
+                    .after(add_animation)
+                    .before(animation_player),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, update_animation_parameters)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                checker_animation_duration
+                    .after(update_animation)
+                    .after(update_animation_parameters),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                cross_fade
+                    .after(update_animation)
+                    .after(animation_player)
+                    .before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -32,6 +66,7 @@ impl Plugin for AnimationHandler {
 ///        target: entity,
 ///        index: number as usize,
 ///        repeat: true,
+///        blend: GRAPH_BLEND,
 ///    }
 /// );
 /// ```
@@ -40,6 +75,31 @@ pub struct ChangeAnimation {
     pub(crate) target: u32,
     pub(crate) index: usize,
     pub(crate) repeat: bool,
+    /// seconds of the cross-fade from the current clip, 0 cuts to the new one
+    pub(crate) blend: f32,
+}
+
+/// Event envoyé quand l'animation d'une créature passe un de ses marqueurs
+/// "landed" est envoyé par creatures::ground, à l'arrivée au sol
+#[derive(Debug)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// Named point inside a clip, given by the creature definitions
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AnimationMarker {
+    /// seconds from the start of the clip, before its end
+    pub time: f32,
+    pub name: String,
+}
+
+impl AnimationMarker {
+    /// Returns true if the playback went through the marker between `start` and `end`
+    fn is_crossed(&self, start: f32, end: f32) -> bool {
+        start <= self.time && self.time < end
+    }
 }
 
 /// Event utilisé pour ajouter une animation
@@ -63,10 +123,32 @@ pub struct TagPlayerScene;
 #[derive(Default)]
 pub struct VecSceneHandle(pub Vec<SceneHandle>);
 
+impl VecSceneHandle {
+    /// Duration of an animation of this type of creature, 0 if it is unknown
+    pub fn duration(&self, type_creature: TypeCreature, index: usize) -> f32 {
+        self.0
+            .iter()
+            .find(|scene_handler| scene_handler.type_creature == type_creature)
+            .and_then(|scene_handler| scene_handler.vec_animations.get_pair(index))
+            .map_or(0.0, |(duration, _)| *duration)
+    }
+
+    /// Markers of an animation of this type of creature
+    pub fn markers(&self, type_creature: TypeCreature, index: usize) -> &[AnimationMarker] {
+        self.0
+            .iter()
+            .find(|scene_handler| scene_handler.type_creature == type_creature)
+            .and_then(|scene_handler| scene_handler.markers.get(&index))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
 /// HashMap contenant un tuple: (duration_animation, handle_animation)
 /// La Hashmap est créée dans la fonction spawn de chaque créature
 /// Updated par add_animation et remove_animation
 /// Utilisée par update_animation
+/// The durations are 0 until the clips are loaded, they are then read from the AnimationClip assets
 #[derive(Clone, Debug)]
 pub struct HashMapAnimationClip(HashMap<usize, (f32, Handle<AnimationClip>)>);
 
@@ -82,10 +164,39 @@ impl HashMapAnimationClip {
     pub fn insert(
         &mut self,
         k: usize,
-        duration: f32,
         handle: Handle<AnimationClip>,
     ) -> Option<(f32, Handle<AnimationClip>)> {
-        self.0.insert(k, (duration, handle))
+        self.0.insert(k, (0.0, handle))
+    }
+
+    /// Copies the duration of each loaded clip
+    fn update_durations(&mut self, clips: &Assets<AnimationClip>) {
+        for (duration, handle) in self.0.values_mut() {
+            if let Some(clip) = clips.get(handle) {
+                *duration = clip.duration();
+            }
+        }
+    }
+
+    /// Gives MISSING_CLIP_DURATION to the clips whose file failed to load
+    /// Returns true if a duration changed
+    fn give_up_missing_clips(
+        &mut self,
+        asset_server: &AssetServer,
+        type_creature: TypeCreature,
+    ) -> bool {
+        let mut changed = false;
+        for (index, (duration, handle)) in self.0.iter_mut() {
+            if *duration > 0.0 {
+                continue;
+            }
+            if asset_server.get_load_state(handle) == LoadState::Failed {
+                warn!("Clip {} of {:?} can not be loaded", index, type_creature);
+                *duration = MISSING_CLIP_DURATION;
+                changed = true;
+            }
+        }
+        changed
     }
 }
 
@@ -97,6 +208,8 @@ pub struct AnimationStopWatch {
     pub index_animation: usize,
     pub time: Timer,
     pub manual_termination: bool,
+    /// the clip starts again when it is over, otherwise it stays on its last frame
+    pub repeat: bool,
 }
 
 impl AnimationStopWatch {
@@ -127,6 +240,12 @@ pub struct SceneHandle {
     //pub vec_animations: Vec<Handle<AnimationClip>>,
     pub vec_animations: HashMapAnimationClip,
 
+    /// indexes of the animations during which the creature can move
+    pub moving_animations: Vec<usize>,
+
+    /// markers of each animation, by index
+    pub markers: HashMap<usize, Vec<AnimationMarker>>,
+
     /// if of the entity containing the scene
     pub creature_entity_id: Option<u32>,
 
@@ -200,15 +319,20 @@ fn link_animations(
 
 /// Une fois que link_animations() a ajouté un AnimationEntityLink :
 /// Lancer la première animation !
+/// Skelly changing of body fades from the pose of his previous body
 fn start_animation(
-    query_entity: Query<Entity, (With<Creature>, Added<AnimationEntityLink>)>,
+    query_entity: Query<
+        (Entity, Option<&PoseSnapshot>),
+        (With<Creature>, Added<AnimationEntityLink>),
+    >,
     mut writer: EventWriter<ChangeAnimation>,
 ) {
-    for entity in query_entity.iter() {
+    for (entity, snapshot) in query_entity.iter() {
         writer.send(ChangeAnimation {
             target: entity.id(),
             index: 0,
             repeat: false,
+            blend: snapshot.map_or(0.0, |_| MODEL_SWAP_BLEND),
         })
     }
 }
@@ -224,47 +348,107 @@ fn start_animation(
 ///         scene_handler_random_creature.id() == event_creature_à_animer.id()
 ///      ```
 fn update_animation(
+    mut commands: Commands,
     mut events: EventReader<ChangeAnimation>,
     scene_handlers: Res<VecSceneHandle>,
-    mut query_player: Query<&mut AnimationPlayer>,
-    mut query_entity: Query<(Entity, &AnimationEntityLink, &mut Creature)>,
+    mut query_player: Query<(&mut AnimationPlayer, Option<&CrossFade>)>,
+    mut query_entity: Query<(
+        Entity,
+        &AnimationEntityLink,
+        &mut Creature,
+        Option<&AnimationGraph>,
+        Option<&PoseSnapshot>,
+    )>,
     mut query_stopwatch: Query<&mut AnimationStopWatch>,
 ) {
     for event in events.iter() {
         // retrouver l'entity
         debug!("Event found! {:#?}", event);
-        for (entity, animation_link, mut creature) in query_entity.iter_mut() {
+        for (entity, animation_link, mut creature, graph, snapshot) in query_entity.iter_mut() {
             if entity.id() == event.target {
                 // on a retrouvé le player associé à l'entité
                 debug!("  > entity trouvé!");
-                for scene_handler in &scene_handlers.0 {
-                    if scene_handler.type_creature == creature.type_creature {
-                        debug!(
-                            "  > scene_handler trouvé pour {:#?}",
-                            scene_handler.type_creature
-                        );
-
-                        if let Ok(mut player) = query_player.get_mut(animation_link.get()) {
-                            let (duration, animation) =
-                                scene_handler.vec_animations.get_pair(event.index).unwrap();
-
-                            creature.current_animation_index.0 = event.index;
+                // les créatures du même type partagent leurs animations
+                let scene_handler = match scene_handlers
+                    .0
+                    .iter()
+                    .find(|scene_handler| scene_handler.type_creature == creature.type_creature)
+                {
+                    Some(scene_handler) => scene_handler,
+                    None => continue,
+                };
+                debug!(
+                    "  > scene_handler trouvé pour {:#?}",
+                    scene_handler.type_creature
+                );
+
+                if let Ok((mut player, fade)) = query_player.get_mut(animation_link.get()) {
+                    let (duration, animation) =
+                        match scene_handler.vec_animations.get_pair(event.index) {
+                            Some(pair) => pair,
+                            None => {
+                                warn!(
+                                    "No animation {} for {:?}",
+                                    event.index, scene_handler.type_creature
+                                );
+                                continue;
+                            }
+                        };
+
+                    // the pose of the previous body, or the clip being left
+                    let current = creature.current_animation_index.0;
+                    let from = if let Some(snapshot) = snapshot {
+                        commands.entity(entity).remove::<PoseSnapshot>();
+                        Some(FadeSource::Pose(snapshot.clone()))
+                    } else if let Some(fade) = fade {
+                        Some(FadeSource::Clip(fade.to.clone()))
+                    } else {
+                        scene_handler
+                            .vec_animations
+                            .get_pair(current)
+                            .map(|(_, handle)| {
+                                FadeSource::Clip(ClipTime {
+                                    handle: handle.clone_weak(),
+                                    elapsed: player.elapsed(),
+                                    repeat: graph.map_or(false, |graph| graph.repeat(current)),
+                                })
+                            })
+                    };
+
+                    creature.current_animation_index.0 = event.index;
+                    creature.can_move = scene_handler.moving_animations.contains(&event.index);
+                    match from {
+                        Some(from) if event.blend > 0.0 => {
+                            player.pause();
+                            commands.entity(animation_link.get()).insert(CrossFade::new(
+                                from,
+                                animation.clone_weak(),
+                                event.repeat,
+                                event.blend,
+                            ));
+                            debug!("Fading!");
+                        }
+                        _ => {
+                            if fade.is_some() {
+                                commands.entity(animation_link.get()).remove::<CrossFade>();
+                            }
+                            player.play(animation.clone_weak());
                             if event.repeat {
-                                player.play(animation.clone_weak()).repeat();
-                                debug!("Playing repeat!");
-                            } else {
-                                player.play(animation.clone_weak());
-                                debug!("Playing!");
+                                player.repeat();
                             }
+                            debug!("Playing!");
+                        }
+                    }
 
-                            for mut stopwatch in query_stopwatch.iter_mut() {
-                                if stopwatch.creature_entity_id == entity.id() {
-                                    stopwatch.index_animation = event.index;
-                                    stopwatch
-                                        .time
-                                        .set_duration(Duration::from_secs_f32(*duration));
-                                }
-                            }
+                    for mut stopwatch in query_stopwatch.iter_mut() {
+                        if stopwatch.creature_entity_id == entity.id() {
+                            stopwatch.index_animation = event.index;
+                            stopwatch.repeat = event.repeat;
+                            stopwatch
+                                .time
+                                .set_duration(Duration::from_secs_f32(*duration));
+                            // the markers are timed from the start of the clip
+                            stopwatch.reset_timer();
                         }
                     }
                 }
@@ -273,71 +457,155 @@ fn update_animation(
     }
 }
 
-/// Utiliser pour trouver la durée d'une AnimationClip
-/// Ne fonctionne que pour le premier élément ajouté dans VecSceneHandle, après 'done' > 2.
-/*
-fn inspect_animation_clip(
-    assets_handle: Res<Assets<AnimationClip>>,
-    scene_handlers: Res<VecSceneHandle>,
-    mut done: Local<usize>,
+/// Once a clip is loaded, or reloaded, the stopwatches use its duration,
+/// the running ones included
+fn update_clip_durations(
+    mut asset_events: EventReader<AssetEvent<AnimationClip>>,
+    clips: Res<Assets<AnimationClip>>,
+    mut vec_scene_handlers: ResMut<VecSceneHandle>,
+    query_creatures: Query<(Entity, &Creature)>,
+    mut query_stopwatch: Query<&mut AnimationStopWatch>,
 ) {
-    if *done > 2 {
-        return
+    let loaded = asset_events.iter().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Created { .. } | AssetEvent::Modified { .. }
+        )
+    });
+    if !loaded {
+        return;
     }
 
-    for scene_handler in &scene_handlers.0 {
-        for ind in 0..scene_handler.vec_animations.0.len() {
-            let (_, handle) = scene_handler.vec_animations.get_pair(ind).unwrap();
-            if let Some(anim) = assets_handle.get(handle)
-            {
-                let st = format!("Start : {:?}", anim);
-                let duration_str_i = (st.as_str()).find("duration").unwrap();
-                let len = st.len();
-
-                info!("Anim index {}, {}", ind, st.get(duration_str_i..len-2).unwrap());
-                *done += 1;
+    for scene_handler in vec_scene_handlers.0.iter_mut() {
+        scene_handler.vec_animations.update_durations(&clips);
+    }
+
+    update_stopwatch_durations(&vec_scene_handlers, &query_creatures, &mut query_stopwatch);
+}
+
+/// A clip which is never loaded would leave its creature stuck on it,
+/// it is given MISSING_CLIP_DURATION instead
+fn give_up_missing_clips(
+    asset_server: Res<AssetServer>,
+    mut vec_scene_handlers: ResMut<VecSceneHandle>,
+    query_creatures: Query<(Entity, &Creature)>,
+    mut query_stopwatch: Query<&mut AnimationStopWatch>,
+) {
+    let mut changed = false;
+    for scene_handler in vec_scene_handlers.0.iter_mut() {
+        changed |= scene_handler
+            .vec_animations
+            .give_up_missing_clips(&asset_server, scene_handler.type_creature);
+    }
+
+    if changed {
+        update_stopwatch_durations(&vec_scene_handlers, &query_creatures, &mut query_stopwatch);
+    }
+}
+
+fn update_stopwatch_durations(
+    vec_scene_handlers: &VecSceneHandle,
+    query_creatures: &Query<(Entity, &Creature)>,
+    query_stopwatch: &mut Query<&mut AnimationStopWatch>,
+) {
+    for mut stopwatch in query_stopwatch.iter_mut() {
+        let creature = query_creatures
+            .iter()
+            .find(|(entity, _)| entity.id() == stopwatch.creature_entity_id);
+        if let Some((_, creature)) = creature {
+            let duration =
+                vec_scene_handlers.duration(creature.type_creature, stopwatch.index_animation);
+            if duration > 0.0 {
+                stopwatch
+                    .time
+                    .set_duration(Duration::from_secs_f32(duration));
             }
         }
     }
-
 }
-*/
 
 /// Récupère les stopwatch
 /// Met à jour les ticks des stopwatch
-/// Si une stopwacth est terminée :
-///     Une animation est terminée
-///     Récupérer la créature de l'animation et appeler sa fonction update_animation() pour choisir la prochaine animation
+/// Envoie un AnimationEvent pour chaque marqueur passé pendant ce tick
+/// Si une stopwacth est terminée, l'animation de sa créature est terminée
+/// Puis évalue l'AnimationGraph de chaque créature pour choisir la prochaine animation
 fn checker_animation_duration(
-    query_entity: Query<(Entity, &Creature)>,
+    scene_handlers: Res<VecSceneHandle>,
+    query_entity: Query<(
+        Entity,
+        &Creature,
+        &AnimationGraph,
+        Option<&AnimationParameters>,
+    )>,
     mut query_stopwatch: Query<&mut AnimationStopWatch>,
     mut event_writer: EventWriter<ChangeAnimation>,
+    mut marker_writer: EventWriter<AnimationEvent>,
     time: Res<Time>,
 ) {
+    let mut finished = HashSet::new();
+    // index of the animation and seconds played during this tick, by creature
+    let mut played = HashMap::new();
     for mut stopwatch in query_stopwatch.iter_mut() {
+        // the clip is not loaded yet, see update_clip_durations
+        if stopwatch.time.duration().is_zero() && !stopwatch.manual_termination {
+            continue;
+        }
+
+        let start = stopwatch.time.elapsed_secs();
         stopwatch.tick(time.delta());
+        played.insert(
+            stopwatch.creature_entity_id,
+            (
+                stopwatch.index_animation,
+                start,
+                stopwatch.time.elapsed_secs(),
+            ),
+        );
 
         if stopwatch.manual_is_over() {
-            // play new animation for the current entity
-            debug!("Timer finished for entity {}", stopwatch.creature_entity_id);
-            stopwatch.reset_timer(); // en attendant que update_animation vienne faire le travail
-
-            for (entity, creature) in query_entity.iter() {
-                if entity.id() == stopwatch.creature_entity_id {
-                    creature.update_animation(
-                        stopwatch.creature_entity_id,
-                        stopwatch.index_animation,
-                        event_writer.borrow_mut(),
-                    );
+            finished.insert(stopwatch.creature_entity_id);
+            // une animation qui ne boucle pas reste terminée jusqu'au prochain ChangeAnimation,
+            // ses marqueurs ne sont envoyés qu'une fois
+            if stopwatch.repeat {
+                debug!("Timer finished for entity {}", stopwatch.creature_entity_id);
+                stopwatch.reset_timer();
+            }
+        }
+    }
+
+    let default_parameters = AnimationParameters::default();
+    for (entity, creature, graph, parameters) in query_entity.iter() {
+        if let Some((index, start, end)) = played.get(&entity.id()) {
+            for marker in scene_handlers.markers(creature.type_creature, *index) {
+                if marker.is_crossed(*start, *end) {
+                    marker_writer.send(AnimationEvent {
+                        entity,
+                        name: marker.name.clone(),
+                    });
                 }
             }
         }
+
+        if let Some((index, repeat)) = graph.next(
+            creature.current_animation_index.0,
+            parameters.unwrap_or(&default_parameters),
+            finished.contains(&entity.id()),
+        ) {
+            event_writer.send(ChangeAnimation {
+                target: entity.id(),
+                index,
+                repeat,
+                blend: GRAPH_BLEND,
+            });
+        }
     }
 }
 
+/// Les clips déjà chargés (par une autre créature du même type) donnent leur durée tout de suite
 fn add_animation(
     mut events: EventReader<AddAnimation>,
     mut vec_scene_handlers: ResMut<VecSceneHandle>,
+    clips: Res<Assets<AnimationClip>>,
     mut commands: Commands,
 ) {
     for event in events.iter() {
@@ -346,7 +614,9 @@ fn add_animation(
             event.scene_handler.creature_entity_id, event.scene_handler.type_creature
         );
 
-        vec_scene_handlers.0.push(event.scene_handler.clone());
+        let mut scene_handler = event.scene_handler.clone();
+        scene_handler.vec_animations.update_durations(&clips);
+        vec_scene_handlers.0.push(scene_handler);
 
         // On ajoute une Stopwatch si on démarre l'animation
         if event.start_animation {
@@ -374,6 +644,7 @@ pub fn spawn_animation_stop_watch(
             index_animation,
             time: Timer::new(Duration::from_secs(1000.0 as u64), false),
             manual_termination: false,
+            repeat: false,
         })
         .insert(Name::new(format!("Stopwatch {}", creature_entity_id)));
 }
@@ -397,3 +668,26 @@ fn remove_animation(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marker_crossed_once() {
+        let marker = AnimationMarker {
+            time: 0.3,
+            name: "footstep".to_string(),
+        };
+
+        assert!(!marker.is_crossed(0.0, 0.2));
+        assert!(marker.is_crossed(0.2, 0.3 + f32::EPSILON));
+        assert!(!marker.is_crossed(0.3 + f32::EPSILON, 0.4));
+        // on the first tick of the clip
+        let start = AnimationMarker {
+            time: 0.0,
+            name: "start".to_string(),
+        };
+        assert!(start.is_crossed(0.0, 0.016));
+    }
+}
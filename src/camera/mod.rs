@@ -1,4 +1,5 @@
 use crate::creatures::Player;
+use crate::editor::EditorState;
 use bevy::{prelude::*, render::camera::ScalingMode};
 
 /// camera distance from the player
@@ -12,7 +13,9 @@ impl Plugin for CameraPlugin {
         app.add_startup_system(setup)
             .insert_resource::<ShiftFromPlayer>(ShiftFromPlayer(5.0))
             //    .add_startup_system(draw_repere)
-            .add_system(camera_following_player);
+            .add_system_set(
+                SystemSet::on_update(EditorState::Off).with_system(camera_following_player),
+            );
     }
 }
 
@@ -0,0 +1,270 @@
+use crate::animations_handler::graph::AnimationParameters;
+use crate::animations_handler::{AnimationEvent, ChangeAnimation, RemoveAnimation, VecSceneHandle};
+use crate::creatures::skelly::SkellyAnimationId;
+use crate::creatures::{Creature, Player, ToDespawn, TypeCreature};
+use crate::input::{Action, InputActions};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Marker of the Attack animation where the blow lands
+const HIT_FRAME: &str = "hit_frame";
+/// Seconds before the blow lands when the Attack clip is not loaded yet
+const ATTACK_HIT_DELAY: f32 = 0.8;
+/// Seconds during which the blow hurts
+const HIT_LIFETIME: f32 = 0.2;
+/// Distance between the attacker and the center of the blow
+const HIT_REACH: f32 = 1.2;
+const HIT_RADIUS: f32 = 0.6;
+const HIT_DAMAGE: u32 = 1;
+/// Seconds of the cross-fade to Hit, the blow is felt at once
+const HIT_BLEND: f32 = 0.05;
+/// Seconds a dead creature lies on the floor before being despawned
+const CORPSE_DELAY: f32 = 3.0;
+
+pub struct CombatPlugin;
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(attack_input)
+            .add_system(spawn_hit_sensors)
+            .add_system(apply_hits)
+            .add_system(despawn_hit_sensors)
+            .add_system(despawn_corpses);
+    }
+}
+
+/// Hit points of a creature which can be hurt
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Returns true if this damage is lethal
+    pub fn take(&mut self, damage: u32) -> bool {
+        self.current = self.current.saturating_sub(damage);
+        self.is_dead()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// Creature swinging, the blow lands on the hit_frame marker of its Attack animation,
+/// at the latest when the animation is over
+#[derive(Component)]
+pub struct Attacking(Timer);
+
+impl Attacking {
+    pub fn new(vec_scene_handlers: &VecSceneHandle, type_creature: TypeCreature) -> Self {
+        let duration =
+            vec_scene_handlers.duration(type_creature, SkellyAnimationId::Attack as usize);
+        let delay = if duration > 0.0 {
+            duration
+        } else {
+            ATTACK_HIT_DELAY
+        };
+        Self(Timer::from_seconds(delay, false))
+    }
+}
+
+/// Short-lived sensor in front of the attacker, hurting every creature it overlaps once
+#[derive(Component)]
+struct HitSensor {
+    owner: Entity,
+    lifetime: Timer,
+    touched: Vec<Entity>,
+}
+
+/// Creature killed, playing Hit then Die
+#[derive(Component)]
+pub struct Dead;
+
+/// Dead creature, despawned when the timer is finished: once Hit and Die are played
+#[derive(Component)]
+struct Corpse(Timer);
+
+/// The Attack action makes Skelly attack, he needs his arms
+/// His AnimationGraph plays Attack while he is Attacking
+fn attack_input(
+    mut commands: Commands,
+    input_actions: Res<InputActions>,
+    vec_scene_handlers: Res<VecSceneHandle>,
+    mut query_player: Query<(Entity, &Creature, &mut Velocity), (With<Player>, Without<Attacking>)>,
+) {
+    if !input_actions.just_pressed(Action::Attack) {
+        return;
+    }
+
+    if let Ok((entity, creature, mut velocity)) = query_player.get_single_mut() {
+        if creature.type_creature != TypeCreature::SkellyFullBody || !creature.can_move {
+            return;
+        }
+
+        velocity.linvel = Vec3::Y * velocity.linvel.y;
+        commands
+            .entity(entity)
+            .insert(Attacking::new(&vec_scene_handlers, creature.type_creature));
+    }
+}
+
+/// Spawns the blow in front of the attacker, along its Direction,
+/// when its animation crosses the hit_frame marker
+fn spawn_hit_sensors(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut animation_events: EventReader<AnimationEvent>,
+    mut query_attackers: Query<(Entity, &Transform, &Creature, &mut Attacking)>,
+) {
+    let hit_frames: Vec<Entity> = animation_events
+        .iter()
+        .filter(|event| event.name == HIT_FRAME)
+        .map(|event| event.entity)
+        .collect();
+
+    for (entity, transform, creature, mut attacking) in query_attackers.iter_mut() {
+        let timeout = attacking.0.tick(time.delta()).finished();
+        if !timeout && !hit_frames.contains(&entity) {
+            continue;
+        }
+        commands.entity(entity).remove::<Attacking>();
+
+        let position =
+            transform.translation + creature.direction.get_vec3().normalize() * HIT_REACH + Vec3::Y;
+        commands
+            .spawn_bundle(PbrBundle {
+                transform: Transform::from_translation(position),
+                ..default()
+            })
+            .insert(Collider::ball(HIT_RADIUS))
+            .insert(Sensor)
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(HitSensor {
+                owner: entity,
+                lifetime: Timer::from_seconds(HIT_LIFETIME, false),
+                touched: Vec::new(),
+            })
+            .insert(Name::new("Hit sensor"));
+    }
+}
+
+/// Hurts the creatures touched by a blow, they play Hit
+/// The collider of a creature is one of its children
+/// Skelly does not die here, he loses a part of his body (see creatures::lose_parts_on_damage)
+fn apply_hits(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut query_sensors: Query<&mut HitSensor>,
+    query_parent: Query<&Parent>,
+    vec_scene_handlers: Res<VecSceneHandle>,
+    mut query_creatures: Query<
+        (
+            &Creature,
+            &mut Health,
+            Option<&mut AnimationParameters>,
+            Option<&Player>,
+        ),
+        Without<Dead>,
+    >,
+    mut event_writer: EventWriter<ChangeAnimation>,
+) {
+    for collision_event in collision_events.iter() {
+        if let CollisionEvent::Started(entity_a, entity_b, _) = collision_event {
+            for (sensor_entity, other) in [(entity_a, entity_b), (entity_b, entity_a)] {
+                let mut sensor = match query_sensors.get_mut(*sensor_entity) {
+                    Ok(sensor) => sensor,
+                    Err(_) => continue,
+                };
+
+                let target = match query_parent.get(*other) {
+                    Ok(parent) if query_creatures.contains(parent.get()) => parent.get(),
+                    _ => *other,
+                };
+                if target == sensor.owner || sensor.touched.contains(&target) {
+                    continue;
+                }
+
+                if let Ok((creature, mut health, parameters, player)) =
+                    query_creatures.get_mut(target)
+                {
+                    sensor.touched.push(target);
+                    // Hit interrupts the swing of the target
+                    commands.entity(target).remove::<Attacking>();
+                    if health.take(HIT_DAMAGE) && player.is_none() {
+                        info!("{:?} killed", target);
+                        // its AnimationGraph plays Die once Hit is over
+                        let duration = [SkellyAnimationId::Hit, SkellyAnimationId::Die]
+                            .into_iter()
+                            .map(|id| {
+                                vec_scene_handlers.duration(creature.type_creature, id as usize)
+                            })
+                            .sum::<f32>()
+                            + CORPSE_DELAY;
+                        commands
+                            .entity(target)
+                            .insert(Dead)
+                            .insert(Corpse(Timer::from_seconds(duration, false)));
+                        // the AI stops, the graph must not walk between Hit and Die
+                        if let Some(mut parameters) = parameters {
+                            parameters.speed = 0.0;
+                        }
+                    }
+                    event_writer.send(ChangeAnimation {
+                        target: target.id(),
+                        index: SkellyAnimationId::Hit as usize,
+                        repeat: false,
+                        blend: HIT_BLEND,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn despawn_hit_sensors(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_sensors: Query<(Entity, &mut HitSensor)>,
+) {
+    for (entity, mut sensor) in query_sensors.iter_mut() {
+        if sensor.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn despawn_corpses(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query_corpses: Query<(Entity, &mut Corpse)>,
+    mut remove_writer: EventWriter<RemoveAnimation>,
+) {
+    for (entity, mut corpse) in query_corpses.iter_mut() {
+        if corpse.0.tick(time.delta()).just_finished() {
+            commands.entity(entity).insert(ToDespawn);
+            remove_writer.send(RemoveAnimation {
+                entity_id: entity.id(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let mut health = Health::new(2);
+
+        assert!(!health.take(1));
+        assert!(health.take(5));
+        assert_eq!(0, health.current);
+        assert!(health.is_dead());
+    }
+}
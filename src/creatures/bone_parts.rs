@@ -1,11 +1,16 @@
+use crate::animations_handler::graph::{AnimationGraph, Condition};
 use crate::animations_handler::{spawn_animation_stop_watch, VecSceneHandle};
 use crate::creatures::{
     BoneTag, Creature, CurrentAnimationIndex, TypeCreature, GLTF_PATH_ARM, GLTF_PATH_BONE,
     GLTF_PATH_CHEST, GLTF_PATH_HEAD, GLTF_PATH_LEG,
 };
-use crate::map::{I_SHIFT, J_SHIFT};
-use crate::{directions, AddAnimation, HashMapAnimationClip, SceneHandle, SkellyAnimationId};
+use crate::map::grid::{grid_to_world, TileMap};
+use crate::map::level::Level;
+use crate::map::{LevelLoad, LevelReady, TagLevelEntity, I_SHIFT, J_SHIFT};
+use crate::trigger_zone::{TriggerAction, TriggerEvent, TriggerFilter, TriggerZone};
+use crate::{directions, AddAnimation, HashMapAnimationClip, SceneHandle};
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
 use bevy_rapier3d::prelude::*;
 use std::borrow::BorrowMut;
 
@@ -14,202 +19,78 @@ static GLTF_PATH_PACK_BONES: &str = "models/stack_bones/low_poly_bone_pile.glb#S
 pub struct BonePlugin;
 impl Plugin for BonePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(load_asset_parts)
-            .add_startup_system(spawn_pack_bones)
-            .add_system_to_stage(CoreStage::PostUpdate, update_sensor_bonepack);
+        app.init_resource::<ConsumedPacks>()
+            .add_startup_system(load_asset_parts)
+            .add_system(spawn_pack_bones)
+            .add_system(spawn_parts_on_trigger);
     }
 }
 
 #[derive(Component)]
 struct TagPackBone;
 
-#[derive(Debug, Component)]
-struct BonePack {
-    consumed: bool,
-    position: Vec3,
-    items: Vec<TypeCreature>,
-}
-
-fn spawn_pack_bones(asset_server: Res<AssetServer>, mut commands: Commands) {
-    let pack_handle = asset_server.load(GLTF_PATH_PACK_BONES);
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Chest,
-            TypeCreature::Leg,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        9.0,
-        7.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        11.0,
-        8.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![TypeCreature::Bone, TypeCreature::Leg],
-        11.0,
-        7.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![TypeCreature::Bone, TypeCreature::Bone, TypeCreature::Bone],
-        13.0,
-        7.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        12.0,
-        4.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        12.0,
-        5.0,
-        pack_handle.clone(),
-    );
+/// Ressource recording the packs already opened in the current level
+/// They stay empty when the level is reloaded
+#[derive(Default)]
+pub struct ConsumedPacks(HashSet<u32>);
 
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        13.0,
-        4.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        13.0,
-        5.0,
-        pack_handle.clone(),
-    );
-
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![TypeCreature::Bone, TypeCreature::Bone],
-        11.0,
-        11.0,
-        pack_handle.clone(),
-    );
+impl ConsumedPacks {
+    pub fn contains(&self, pack: u32) -> bool {
+        self.0.contains(&pack)
+    }
+}
 
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Arm,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        14.0,
-        12.0,
-        pack_handle.clone(),
-    );
+fn spawn_pack_bones(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut consumed_packs: ResMut<ConsumedPacks>,
+) {
+    for LevelReady(handle, load) in level_events.iter() {
+        if *load == LevelLoad::New {
+            consumed_packs.0.clear();
+        }
 
-    generate_one_pack(
-        commands.borrow_mut(),
-        vec![
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Arm,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-            TypeCreature::Bone,
-        ],
-        14.0,
-        3.0,
-        pack_handle,
-    );
+        if let Some(level) = levels.get(handle) {
+            let pack_handle = asset_server.load(GLTF_PATH_PACK_BONES);
+
+            for pack in &level.packs {
+                let position = grid_to_world(pack.position.0, pack.position.1);
+                // an opened pack is spawned without its sensor
+                let actions = if consumed_packs.contains(pack.id) {
+                    None
+                } else {
+                    let mut actions = vec![TriggerAction::SpawnParts {
+                        pack: pack.id,
+                        position,
+                        items: pack.items.clone(),
+                    }];
+                    // keys are not spawned, they go straight to the inventory
+                    actions.extend(pack.keys.iter().cloned().map(TriggerAction::GiveKey));
+                    Some(actions)
+                };
+
+                generate_one_pack(
+                    commands.borrow_mut(),
+                    actions,
+                    position,
+                    pack.items.len(),
+                    pack_handle.clone(),
+                );
+            }
+        }
+    }
 }
 
 fn generate_one_pack(
     commands: &mut Commands,
-    items: Vec<TypeCreature>,
-    i: f32,
-    j: f32,
+    actions: Option<Vec<TriggerAction>>,
+    position: Vec3,
+    nb_items: usize,
     handle: Handle<Scene>,
 ) {
-    let position = i * I_SHIFT + j * J_SHIFT;
-
-    let bonepack = BonePack {
-        consumed: false,
-        position,
-        items,
-    };
+    let scale = Vec3::ONE * 0.005 * nb_items as f32;
 
     commands
         .spawn_bundle(PbrBundle {
@@ -222,71 +103,67 @@ fn generate_one_pack(
                 transform: Transform {
                     translation: Vec3::new(0.0, 0.0, 0.0),
                     rotation: Default::default(),
-                    scale: Vec3::ONE * 0.005 * bonepack.items.len() as f32,
+                    scale,
                 },
                 ..default()
             });
         })
         .with_children(|parent| {
-            parent
-                .spawn_bundle(PbrBundle {
-                    transform: Transform {
-                        translation: Vec3::new(-0.6, 1.0, -0.5),
-                        rotation: Default::default(),
-                        scale: Vec3::ONE,
-                    },
-                    ..default()
-                })
-                .insert(Collider::cone(0.05, 1.0))
-                .insert(Sensor)
-            //.insert(Sensor)
-            ;
+            if let Some(actions) = actions {
+                parent
+                    .spawn_bundle(PbrBundle {
+                        transform: Transform {
+                            translation: Vec3::new(-0.6, 1.0, -0.5),
+                            rotation: Default::default(),
+                            scale: Vec3::ONE,
+                        },
+                        ..default()
+                    })
+                    .insert(Collider::cone(0.05, 1.0))
+                    .insert(Sensor)
+                    .insert(TriggerZone::new(actions).with_filter(TriggerFilter::Player));
+            }
         })
         .insert(TagPackBone)
-        .insert(bonepack);
+        .insert(TagLevelEntity);
 }
 
-fn update_sensor_bonepack(
-    parent_query: Query<&Parent>,
-    mut query_bone: Query<&mut BonePack, With<TagPackBone>>,
-    mut command: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
-
+/// Handles the SpawnParts action of the bone packs
+fn spawn_parts_on_trigger(
+    mut commands: Commands,
+    mut trigger_events: EventReader<TriggerEvent>,
     vec_scene_handlers: Res<VecSceneHandle>,
+    mut consumed_packs: ResMut<ConsumedPacks>,
 ) {
-    for collision_event in collision_events.iter() {
-        if let CollisionEvent::Started(child_a, child_b, _) = collision_event {
-            for entity in [child_a, child_b] {
-                if let Ok(parent) = parent_query.get(*entity) {
-                    if let Ok(mut bonepack) = query_bone.get_mut(parent.get()) {
-                        spawn_parts_from_pack(
-                            command.borrow_mut(),
-                            &mut bonepack,
-                            &vec_scene_handlers,
-                        );
-                    }
-                }
-            }
+    for TriggerEvent(action) in trigger_events.iter() {
+        if let TriggerAction::SpawnParts {
+            pack,
+            position,
+            items,
+        } = action
+        {
+            consumed_packs.0.insert(*pack);
+            spawn_parts_from_pack(commands.borrow_mut(), *position, items, &vec_scene_handlers);
         }
     }
 }
 
+/// Parts are spawned halfway to the four neighbouring tiles
+fn part_offsets() -> [Vec3; 4] {
+    [J_SHIFT / 2.0, I_SHIFT / 2.0, -I_SHIFT / 2.0, -J_SHIFT / 2.0]
+}
+
 fn spawn_parts_from_pack(
     commands: &mut Commands,
-    mut bonepack: &mut BonePack,
+    pack_position: Vec3,
+    items: &[TypeCreature],
     vec_scene_handlers: &Res<VecSceneHandle>,
 ) {
-    if bonepack.consumed {
-        return;
-    }
-
-    let pack_position = bonepack.position;
-
-    let relative_positon = [J_SHIFT / 2.0, I_SHIFT / 2.0, -I_SHIFT / 2.0, -J_SHIFT / 2.0];
+    let relative_positon = part_offsets();
 
     let mut index = 0;
 
-    for creature in &bonepack.items {
+    for creature in items {
         spawn_part(
             commands,
             vec_scene_handlers,
@@ -295,8 +172,35 @@ fn spawn_parts_from_pack(
         );
         index = (index + 1) % 4;
     }
+}
 
-    bonepack.consumed = true;
+/// Scatters the parts lost by Skelly around him, only where there is a floor
+/// Without any floor around, they are lost
+pub(crate) fn scatter_parts(
+    commands: &mut Commands,
+    vec_scene_handlers: &Res<VecSceneHandle>,
+    tile_map: &TileMap,
+    position: Vec3,
+    items: &[TypeCreature],
+) {
+    let position = Vec3::new(position.x, 0.0, position.z);
+    let spots: Vec<Vec3> = part_offsets()
+        .into_iter()
+        .map(|offset| position + offset)
+        .filter(|spot| tile_map.tile_at(*spot).is_some())
+        .collect();
+    if spots.is_empty() {
+        return;
+    }
+
+    for (index, creature) in items.iter().enumerate() {
+        spawn_part(
+            commands,
+            vec_scene_handlers,
+            spots[index % spots.len()],
+            *creature,
+        );
+    }
 }
 
 /// Loads assets
@@ -360,11 +264,13 @@ fn helper_load_asset(
 
     let id = 0;
     let handle = asset_server.load(format!("{}#Animation{}", scene_path, id as usize).as_str());
-    hm_animations.insert(id as usize, SkellyAnimationId::Idle.get_duration(), handle);
+    hm_animations.insert(id as usize, handle);
 
     let scene = SceneHandle {
         handle: asset_scene_handle,
         vec_animations: hm_animations,
+        moving_animations: vec![],
+        markers: HashMap::default(),
         creature_entity_id: None,
         type_creature,
         activated: true,
@@ -378,7 +284,7 @@ fn helper_load_asset(
 }
 
 /// Spawn the part with Commands and create a stopwatch
-fn spawn_part(
+pub(crate) fn spawn_part(
     commands: &mut Commands,
     vec_scene_handlers: &Res<VecSceneHandle>,
     position: Vec3,
@@ -455,6 +361,8 @@ fn spawn_part(
                     current_animation_index: CurrentAnimationIndex(0),
                     can_move: false,
                 })
+                // the single animation of the part loops
+                .insert(AnimationGraph::default().with_state(0, true, &[(Condition::Finished, 0)]))
                 .insert(Name::new(format!("{:#?}", type_creature)))
                 .id();
 
@@ -0,0 +1,306 @@
+use crate::animations_handler::{AnimationMarker, HashMapAnimationClip, SceneHandle};
+use crate::creatures::skelly::SkellyAnimationId;
+use crate::creatures::TypeCreature;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use bevy_rapier3d::prelude::Collider;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Definition file of each creature, in the assets folder
+static CREATURE_DEFINITIONS: &[(TypeCreature, &str)] = &[
+    (
+        TypeCreature::SkellyFullBody,
+        "creatures/skelly_full_body.creature.ron",
+    ),
+    (
+        TypeCreature::SkellyHalf,
+        "creatures/skelly_half_body.creature.ron",
+    ),
+    (
+        TypeCreature::SkellyOnlyHead,
+        "creatures/skelly_head.creature.ron",
+    ),
+    (TypeCreature::Guard, "creatures/guard.creature.ron"),
+];
+
+pub struct DefinitionPlugin;
+impl Plugin for DefinitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<CreatureDefinition>()
+            .init_asset_loader::<CreatureDefinitionLoader>()
+            .init_resource::<CreatureDefinitions>()
+            .add_startup_system(load_creature_definitions)
+            .add_system(store_creature_definitions);
+    }
+}
+
+/// Transform of the scene, child of the creature entity
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SceneTransform {
+    #[serde(default)]
+    pub translation: (f32, f32, f32),
+    pub scale: f32,
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        Self {
+            translation: (0.0, 0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+impl SceneTransform {
+    pub fn transform(&self) -> Transform {
+        let (x, y, z) = self.translation;
+        Transform {
+            translation: Vec3::new(x, y, z),
+            scale: Vec3::ONE * self.scale,
+            ..default()
+        }
+    }
+}
+
+/// Collider of the creature, spawned as a child at `offset`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum ColliderShape {
+    Cuboid {
+        half_extents: (f32, f32, f32),
+        offset: (f32, f32, f32),
+    },
+    Ball {
+        radius: f32,
+        offset: (f32, f32, f32),
+    },
+}
+
+impl ColliderShape {
+    pub fn collider(&self) -> Collider {
+        match self {
+            ColliderShape::Cuboid {
+                half_extents: (x, y, z),
+                ..
+            } => Collider::cuboid(*x, *y, *z),
+            ColliderShape::Ball { radius, .. } => Collider::ball(*radius),
+        }
+    }
+
+    pub fn offset(&self) -> Vec3 {
+        let (x, y, z) = match self {
+            ColliderShape::Cuboid { offset, .. } | ColliderShape::Ball { offset, .. } => *offset,
+        };
+        Vec3::new(x, y, z)
+    }
+}
+
+/// Speeds of a creature, from its definition
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct MovementSpeed {
+    pub walk: f32,
+    pub run: f32,
+}
+
+/// Asset describing a creature: its model, animations, speeds and collider
+/// Re-rigging a model only changes its definition file
+#[derive(Debug, Clone, PartialEq, Deserialize, TypeUuid)]
+#[uuid = "ba607695-8d6e-4bc3-8137-8b0eca899556"]
+pub struct CreatureDefinition {
+    /// glTF file holding the scene and the animation clips
+    pub scene: String,
+    #[serde(default)]
+    pub scene_transform: SceneTransform,
+    /// index of the clip in the glTF file, loaded from "scene#Animation{index}"
+    /// the durations are read from the clips
+    pub animations: HashMap<SkellyAnimationId, usize>,
+    /// animations during which the creature can be moved
+    pub can_move: Vec<SkellyAnimationId>,
+    /// named points of the animations, sent as AnimationEvent when the playback crosses them
+    #[serde(default)]
+    pub markers: HashMap<SkellyAnimationId, Vec<AnimationMarker>>,
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub collider: ColliderShape,
+}
+
+impl CreatureDefinition {
+    pub fn can_move(&self, animation: SkellyAnimationId) -> bool {
+        self.can_move.contains(&animation)
+    }
+
+    pub fn speed(&self) -> MovementSpeed {
+        MovementSpeed {
+            walk: self.walk_speed,
+            run: self.run_speed,
+        }
+    }
+
+    /// Loads the scene and the animation clips, keyed by SkellyAnimationId
+    pub fn scene_handle(
+        &self,
+        asset_server: &Res<AssetServer>,
+        type_creature: TypeCreature,
+    ) -> SceneHandle {
+        let mut hm_animations = HashMapAnimationClip::new();
+        for (id, index) in &self.animations {
+            let handle = asset_server.load(format!("{}#Animation{}", self.scene, index).as_str());
+            hm_animations.insert(*id as usize, handle);
+        }
+
+        SceneHandle {
+            handle: asset_server.load(format!("{}#Scene0", self.scene).as_str()),
+            vec_animations: hm_animations,
+            moving_animations: self.can_move.iter().map(|id| *id as usize).collect(),
+            markers: self
+                .markers
+                .iter()
+                .map(|(id, markers)| (*id as usize, markers.clone()))
+                .collect(),
+            creature_entity_id: None,
+            type_creature,
+            activated: true,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CreatureDefinitionLoader;
+
+impl AssetLoader for CreatureDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition = ron::de::from_bytes::<CreatureDefinition>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["creature.ron"]
+    }
+}
+
+/// Ressource holding the loaded definition of each creature
+/// Levels are only spawned once every definition is loaded
+#[derive(Default)]
+pub struct CreatureDefinitions {
+    handles: Vec<(TypeCreature, Handle<CreatureDefinition>)>,
+    loaded: HashMap<TypeCreature, CreatureDefinition>,
+}
+
+impl CreatureDefinitions {
+    /// Panics if the definition is not loaded yet, see is_complete()
+    pub fn get(&self, type_creature: TypeCreature) -> &CreatureDefinition {
+        self.loaded
+            .get(&type_creature)
+            .unwrap_or_else(|| panic!("No definition loaded for {:?}", type_creature))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.loaded.len() == CREATURE_DEFINITIONS.len()
+    }
+}
+
+fn load_creature_definitions(
+    asset_server: Res<AssetServer>,
+    mut definitions: ResMut<CreatureDefinitions>,
+) {
+    definitions.handles = CREATURE_DEFINITIONS
+        .iter()
+        .map(|(type_creature, path)| (*type_creature, asset_server.load(*path)))
+        .collect();
+}
+
+/// Creatures spawned after a definition file is modified use the new definition
+fn store_creature_definitions(
+    mut asset_events: EventReader<AssetEvent<CreatureDefinition>>,
+    assets: Res<Assets<CreatureDefinition>>,
+    mut definitions: ResMut<CreatureDefinitions>,
+) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            let type_creature = definitions
+                .handles
+                .iter()
+                .find(|(_, definition_handle)| definition_handle == handle)
+                .map(|(type_creature, _)| *type_creature);
+
+            if let (Some(type_creature), Some(definition)) = (type_creature, assets.get(handle)) {
+                info!("Definition of {:?} loaded", type_creature);
+                definitions.loaded.insert(type_creature, definition.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> CreatureDefinition {
+        ron::de::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_definitions_parse() {
+        let full_body = parse(include_str!(
+            "../../assets/creatures/skelly_full_body.creature.ron"
+        ));
+        let half_body = parse(include_str!(
+            "../../assets/creatures/skelly_half_body.creature.ron"
+        ));
+        let head = parse(include_str!(
+            "../../assets/creatures/skelly_head.creature.ron"
+        ));
+        let guard = parse(include_str!("../../assets/creatures/guard.creature.ron"));
+
+        // every animation of the skeleton, but None
+        assert_eq!(11, full_body.animations.len());
+        assert_eq!(full_body.animations, guard.animations);
+        assert_eq!(full_body.collider, half_body.collider);
+        assert_eq!(full_body.collider, head.collider);
+        // the head can not run
+        assert_eq!(head.walk_speed, head.run_speed);
+    }
+
+    #[test]
+    fn test_can_move() {
+        let full_body = parse(include_str!(
+            "../../assets/creatures/skelly_full_body.creature.ron"
+        ));
+
+        for id in [
+            SkellyAnimationId::Idle,
+            SkellyAnimationId::Walk,
+            SkellyAnimationId::Run,
+        ] {
+            assert!(full_body.can_move(id));
+        }
+        assert!(!full_body.can_move(SkellyAnimationId::Spawn));
+        assert!(!full_body.can_move(SkellyAnimationId::LookingAround));
+    }
+
+    #[test]
+    fn test_markers() {
+        let full_body = parse(include_str!(
+            "../../assets/creatures/skelly_full_body.creature.ron"
+        ));
+        let head = parse(include_str!(
+            "../../assets/creatures/skelly_head.creature.ron"
+        ));
+
+        let attack = &full_body.markers[&SkellyAnimationId::Attack];
+        assert_eq!(1, attack.len());
+        assert_eq!("hit_frame", attack[0].name);
+        assert_eq!(1.1, attack[0].time);
+        // the head has no feet
+        assert!(head.markers.is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::animations_handler::AnimationEvent;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// The ray starts this high above the feet of the creature, so that it starts above a step
+const GROUND_RAY_START: f32 = 0.5;
+/// A floor this close under the feet still counts as ground
+const GROUND_TOLERANCE: f32 = 0.2;
+/// Seconds in the air before a creature is considered falling, walking down a step is shorter
+const FALL_DELAY: f32 = 0.15;
+/// AnimationEvent sent when a falling creature reaches the ground
+const LANDED: &str = "landed";
+
+pub struct GroundPlugin;
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(detect_ground);
+    }
+}
+
+/// Creature standing on something, exclusive with Airborne
+#[derive(Component)]
+pub struct Grounded;
+
+/// Creature with nothing under its feet, exclusive with Grounded
+/// Creatures checking the ground are spawned with it
+#[derive(Component, Default)]
+pub struct Airborne {
+    pub seconds: f32,
+}
+
+impl Airborne {
+    pub fn is_falling(&self) -> bool {
+        self.seconds > FALL_DELAY
+    }
+}
+
+/// Casts a ray down from each creature, its own colliders and the sensors are ignored
+fn detect_ground(
+    mut commands: Commands,
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut event_writer: EventWriter<AnimationEvent>,
+    mut query_creatures: Query<
+        (Entity, &Transform, Option<&mut Airborne>),
+        Or<(With<Grounded>, With<Airborne>)>,
+    >,
+) {
+    for (entity, transform, airborne) in query_creatures.iter_mut() {
+        let origin = transform.translation + Vec3::Y * GROUND_RAY_START;
+        let filter = QueryFilter::new()
+            .exclude_rigid_body(entity)
+            .exclude_sensors();
+        let on_ground = rapier_context
+            .cast_ray(
+                origin,
+                -Vec3::Y,
+                GROUND_RAY_START + GROUND_TOLERANCE,
+                true,
+                filter,
+            )
+            .is_some();
+
+        match (on_ground, airborne) {
+            (true, Some(airborne)) => {
+                if airborne.is_falling() {
+                    event_writer.send(AnimationEvent {
+                        entity,
+                        name: LANDED.to_string(),
+                    });
+                }
+                commands
+                    .entity(entity)
+                    .remove::<Airborne>()
+                    .insert(Grounded);
+            }
+            (false, Some(mut airborne)) => airborne.seconds += time.delta_seconds(),
+            (false, None) => {
+                commands
+                    .entity(entity)
+                    .remove::<Grounded>()
+                    .insert(Airborne::default());
+            }
+            (true, None) => {}
+        }
+    }
+}
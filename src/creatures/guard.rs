@@ -0,0 +1,463 @@
+use crate::animations_handler::graph::{AnimationGraph, AnimationParameters, Condition};
+use crate::animations_handler::{AddAnimation, RemoveAnimation, VecSceneHandle};
+use crate::combat::{Attacking, Dead, Health};
+use crate::creatures::definition::{CreatureDefinitions, MovementSpeed};
+use crate::creatures::ground::Airborne;
+use crate::creatures::skelly::SkellyAnimationId;
+use crate::creatures::{
+    Creature, CreatureTrait, CurrentAnimationIndex, Player, ToDespawn, TypeCreature,
+};
+use crate::directions::{self, Direction};
+use crate::map::grid::{grid_to_world, TileMap};
+use crate::map::level::{Level, LevelGuard};
+use crate::map::navigation::NavGrid;
+use crate::map::{LevelLoad, LevelReady};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Within this distance, a guard leaves its patrol to chase Skelly
+const CHASE_DISTANCE: f32 = 10.0;
+/// Beyond this distance, a chasing guard goes back to its patrol
+const GIVE_UP_DISTANCE: f32 = 16.0;
+/// A chasing guard stops this close to Skelly
+const REACH_DISTANCE: f32 = 1.5;
+/// A waypoint, or a tile of the path, is reached this close to its center
+const STEP_DISTANCE: f32 = 0.5;
+/// Seconds between two path queries
+const REPATH_DELAY: f32 = 0.5;
+const GUARD_HEALTH: u32 = 2;
+
+pub struct GuardPlugin;
+impl Plugin for GuardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelGuards>()
+            .add_system(spawn_guards)
+            .add_system(guard_ai);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum GuardState {
+    Patrol,
+    Chase,
+}
+
+impl GuardState {
+    /// `distance` to Skelly, None when he can not be chased (falling, not spawned)
+    fn next(self, distance: Option<f32>) -> Self {
+        match (self, distance) {
+            (GuardState::Patrol, Some(distance)) if distance < CHASE_DISTANCE => GuardState::Chase,
+            (GuardState::Chase, Some(distance)) if distance < GIVE_UP_DISTANCE => GuardState::Chase,
+            _ => GuardState::Patrol,
+        }
+    }
+}
+
+/// Component of a guard, walking from waypoint to waypoint until Skelly comes close
+#[derive(Component)]
+pub struct GuardAi {
+    state: GuardState,
+    waypoints: Vec<Vec3>,
+    next_waypoint: usize,
+    /// headings and tile centers to walk to, given by the NavGrid
+    path: Vec<(Direction, Vec3)>,
+    repath: Timer,
+}
+
+/// Distance on the floor, ignoring the height
+fn flat_distance(a: Vec3, b: Vec3) -> f32 {
+    Vec3::new(a.x - b.x, 0.0, a.z - b.z).length()
+}
+
+pub(crate) struct Guard;
+
+impl Guard {
+    /// Spawns a guard on the first waypoint
+    fn spawn_patrol(
+        commands: &mut Commands,
+        asset_server: &Res<AssetServer>,
+        event_writer: &mut EventWriter<AddAnimation>,
+        definitions: &CreatureDefinitions,
+        waypoints: Vec<Vec3>,
+    ) {
+        let position = match waypoints.first() {
+            Some(position) => *position,
+            None => return,
+        };
+        let definition = definitions.get(TypeCreature::Guard);
+        let mut scene_handle = definition.scene_handle(asset_server, TypeCreature::Guard);
+
+        let guard_id = commands
+            .spawn()
+            .insert_bundle(PbrBundle {
+                transform: Transform {
+                    translation: position,
+                    rotation: Quat::from_rotation_y(Direction::Down.get_angle()),
+                    scale: Vec3::ONE,
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(SceneBundle {
+                    scene: scene_handle.handle.clone(),
+                    transform: definition.scene_transform.transform(),
+                    ..default()
+                });
+            })
+            .with_children(|children| {
+                children
+                    .spawn()
+                    .insert(definition.collider.collider())
+                    .insert_bundle(PbrBundle {
+                        transform: Transform::from_translation(definition.collider.offset()),
+                        ..default()
+                    });
+            })
+            .insert(RigidBody::Dynamic)
+            .insert(
+                LockedAxes::ROTATION_LOCKED_X
+                    | LockedAxes::ROTATION_LOCKED_Z
+                    | LockedAxes::ROTATION_LOCKED_Y,
+            )
+            .insert(Velocity::default())
+            .insert(Creature {
+                type_creature: TypeCreature::Guard,
+                direction: Direction::Down,
+                direction_vec3: Direction::Down.get_vec3(),
+                current_animation_index: CurrentAnimationIndex::from(SkellyAnimationId::Idle),
+                can_move: definition.can_move(SkellyAnimationId::Idle),
+            })
+            .insert(definition.speed())
+            .insert(Guard::animation_graph(TypeCreature::Guard))
+            .insert(AnimationParameters::default())
+            .insert(Health::new(GUARD_HEALTH))
+            .insert(Airborne::default())
+            .insert(GuardAi {
+                state: GuardState::Patrol,
+                waypoints,
+                next_waypoint: 0,
+                path: Vec::new(),
+                repath: Timer::from_seconds(REPATH_DELAY, true),
+            })
+            .insert(Name::new("Guard"))
+            .id();
+
+        scene_handle.creature_entity_id = Some(guard_id.id());
+        event_writer.send(AddAnimation {
+            scene_handler: scene_handle,
+            target: Some(guard_id.id()),
+            start_animation: true,
+        });
+    }
+}
+
+impl CreatureTrait for Guard {
+    /// A guard without waypoints stays where it is spawned
+    fn spawn(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        mut event_writer: EventWriter<AddAnimation>,
+        definitions: &CreatureDefinitions,
+        position: Vec3,
+    ) {
+        Guard::spawn_patrol(
+            &mut commands,
+            &asset_server,
+            &mut event_writer,
+            definitions,
+            vec![position],
+        );
+    }
+
+    /// Walk, Run and Idle loop, a dead guard plays Die after Hit and stays on its last frame,
+    /// every other animation goes back to Idle
+    fn animation_graph(_: TypeCreature) -> AnimationGraph {
+        use Condition::*;
+        use SkellyAnimationId::{
+            Attack, Die, Fall, Hanged, Hit, Idle, LookingAround, Run, Spawn, Walk, Yell,
+        };
+
+        let mut graph = AnimationGraph::default()
+            .with_state(
+                Idle as usize,
+                true,
+                &[
+                    (Attacking, Attack as usize),
+                    (Running, Run as usize),
+                    (Walking, Walk as usize),
+                ],
+            )
+            .with_state(
+                Walk as usize,
+                true,
+                &[
+                    (Attacking, Attack as usize),
+                    (Still, Idle as usize),
+                    (Running, Run as usize),
+                ],
+            )
+            .with_state(
+                Run as usize,
+                true,
+                &[
+                    (Attacking, Attack as usize),
+                    (Still, Idle as usize),
+                    (Walking, Walk as usize),
+                ],
+            );
+        for animation in [Spawn, LookingAround, Attack, Yell, Fall, Hanged] {
+            graph = graph.with_state(animation as usize, false, &[(Finished, Idle as usize)]);
+        }
+        graph.with_state(
+            Hit as usize,
+            false,
+            &[(Killed, Die as usize), (Finished, Idle as usize)],
+        )
+    }
+}
+
+/// Ressource holding the guards of the level file, as they were last spawned
+#[derive(Default)]
+struct LevelGuards(Vec<LevelGuard>);
+
+fn patrol_waypoints(guard: &LevelGuard) -> Vec<Vec3> {
+    guard
+        .waypoints
+        .iter()
+        .map(|(i, j)| grid_to_world(*i, *j))
+        .collect()
+}
+
+/// Returns the guards removed from `old` and the ones added in `new`,
+/// a guard whose waypoints were edited is both removed and added
+fn diff_guards(old: &[LevelGuard], new: &[LevelGuard]) -> (Vec<LevelGuard>, Vec<LevelGuard>) {
+    let mut removed = old.to_vec();
+    let mut added = vec![];
+    for guard in new {
+        match removed.iter().position(|old_guard| old_guard == guard) {
+            Some(index) => {
+                removed.swap_remove(index);
+            }
+            None => added.push(guard.clone()),
+        }
+    }
+    (removed, added)
+}
+
+fn despawn_guard(
+    commands: &mut Commands,
+    remove_writer: &mut EventWriter<RemoveAnimation>,
+    entity: Entity,
+) {
+    commands.entity(entity).insert(ToDespawn);
+    remove_writer.send(RemoveAnimation {
+        entity_id: entity.id(),
+    });
+}
+
+/// Replaces the guards of the previous level
+/// A reloaded level only replaces the guards whose waypoints were edited,
+/// the other ones keep patrolling and the killed ones stay dead
+fn spawn_guards(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut event_writer: EventWriter<AddAnimation>,
+    mut remove_writer: EventWriter<RemoveAnimation>,
+    definitions: Res<CreatureDefinitions>,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut level_guards: ResMut<LevelGuards>,
+    query_guards: Query<(Entity, &GuardAi)>,
+) {
+    for LevelReady(handle, load) in level_events.iter() {
+        if let Some(level) = levels.get(handle) {
+            let added = match load {
+                LevelLoad::New => {
+                    for (entity, _) in query_guards.iter() {
+                        despawn_guard(&mut commands, &mut remove_writer, entity);
+                    }
+                    level.guards.clone()
+                }
+                LevelLoad::Reload => {
+                    let (removed, added) = diff_guards(&level_guards.0, &level.guards);
+                    let mut live_guards: Vec<_> = query_guards.iter().collect();
+                    for guard in &removed {
+                        let waypoints = patrol_waypoints(guard);
+                        if let Some(index) = live_guards
+                            .iter()
+                            .position(|(_, guard_ai)| guard_ai.waypoints == waypoints)
+                        {
+                            let (entity, _) = live_guards.swap_remove(index);
+                            despawn_guard(&mut commands, &mut remove_writer, entity);
+                        }
+                    }
+                    added
+                }
+            };
+            level_guards.0 = level.guards.clone();
+
+            for guard in &added {
+                Guard::spawn_patrol(
+                    &mut commands,
+                    &asset_server,
+                    &mut event_writer,
+                    &definitions,
+                    patrol_waypoints(guard),
+                );
+            }
+        }
+    }
+}
+
+/// Moves the guards along the NavGrid, towards their next waypoint or towards Skelly
+/// A guard reaching Skelly attacks him, its AnimationGraph follows its speed
+fn guard_ai(
+    mut commands: Commands,
+    time: Res<Time>,
+    nav_grid: Res<NavGrid>,
+    tile_map: Res<TileMap>,
+    vec_scene_handlers: Res<VecSceneHandle>,
+    query_player: Query<&Transform, (With<Player>, Without<GuardAi>)>,
+    mut query_guards: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Velocity,
+            &mut Creature,
+            &MovementSpeed,
+            &mut AnimationParameters,
+            &mut GuardAi,
+        ),
+        (Without<Player>, Without<Dead>, Without<Attacking>),
+    >,
+) {
+    // Skelly is only chased while he is on the floor
+    let player_position = query_player
+        .get_single()
+        .ok()
+        .map(|player_transform| player_transform.translation)
+        .filter(|position| tile_map.tile_at(*position).is_some());
+
+    for (entity, mut transform, mut velocity, mut creature, speed, mut parameters, mut guard) in
+        query_guards.iter_mut()
+    {
+        let position = transform.translation;
+        let distance = player_position.map(|player| flat_distance(position, player));
+
+        let mut repath = guard.repath.tick(time.delta()).just_finished();
+
+        let state = guard.state.next(distance);
+        if state != guard.state {
+            info!("Guard {:?}: {:?}", entity, state);
+            guard.state = state;
+            repath = true;
+        }
+
+        let target = match (state, player_position) {
+            (GuardState::Chase, Some(player)) => player,
+            _ => {
+                let waypoint = guard.waypoints[guard.next_waypoint];
+                if flat_distance(position, waypoint) < STEP_DISTANCE {
+                    guard.next_waypoint = (guard.next_waypoint + 1) % guard.waypoints.len();
+                    repath = true;
+                }
+                guard.waypoints[guard.next_waypoint]
+            }
+        };
+
+        if repath {
+            guard.path = nav_grid
+                .find_world_path(position, target)
+                .unwrap_or_default();
+        }
+        guard
+            .path
+            .retain(|(_, step)| flat_distance(position, *step) >= STEP_DISTANCE);
+
+        let reached_player = state == GuardState::Chase
+            && distance.map_or(false, |distance| distance < REACH_DISTANCE);
+        let can_move = creature.can_move;
+
+        if let (true, Some(player)) = (reached_player && can_move, player_position) {
+            let direction = directions::nearest_direction(player - position);
+            creature.direction = direction;
+            creature.direction_vec3 = direction.get_vec3();
+            transform.rotation = Quat::from_rotation_y(direction.get_angle());
+            velocity.linvel = Vec3::Y * velocity.linvel.y;
+            parameters.speed = 0.0;
+
+            commands
+                .entity(entity)
+                .insert(Attacking::new(&vec_scene_handlers, creature.type_creature));
+            continue;
+        }
+
+        parameters.running = state == GuardState::Chase;
+        parameters.speed = match guard.path.first() {
+            Some((direction, step)) if can_move && !reached_player => {
+                let speed = match state {
+                    GuardState::Patrol => speed.walk,
+                    GuardState::Chase => speed.run,
+                };
+                let heading = Vec3::new(step.x - position.x, 0.0, step.z - position.z).normalize();
+                velocity.linvel = heading * speed + Vec3::Y * velocity.linvel.y;
+
+                creature.direction = *direction;
+                creature.direction_vec3 = direction.get_vec3();
+                transform.rotation = transform
+                    .rotation
+                    .lerp(Quat::from_rotation_y(direction.get_angle()), 0.1);
+                speed
+            }
+            _ => {
+                velocity.linvel = Vec3::Y * velocity.linvel.y;
+                0.0
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chase_until_too_far() {
+        let state = GuardState::Patrol;
+
+        assert_eq!(GuardState::Patrol, state.next(None));
+        assert_eq!(GuardState::Patrol, state.next(Some(CHASE_DISTANCE + 1.0)));
+
+        let state = state.next(Some(CHASE_DISTANCE - 1.0));
+        assert_eq!(GuardState::Chase, state);
+        // keeps chasing between both distances
+        assert_eq!(GuardState::Chase, state.next(Some(CHASE_DISTANCE + 1.0)));
+        assert_eq!(GuardState::Patrol, state.next(Some(GIVE_UP_DISTANCE)));
+        assert_eq!(GuardState::Patrol, state.next(None));
+    }
+
+    #[test]
+    fn test_diff_edited_guards() {
+        let kept = LevelGuard {
+            waypoints: vec![(1.0, 1.0), (1.0, 4.0)],
+        };
+        let edited = LevelGuard {
+            waypoints: vec![(3.0, 2.0), (5.0, 2.0)],
+        };
+        let moved = LevelGuard {
+            waypoints: vec![(3.0, 2.0), (6.0, 2.0)],
+        };
+
+        let (removed, added) = diff_guards(
+            &[kept.clone(), edited.clone()],
+            &[moved.clone(), kept.clone()],
+        );
+        assert_eq!(vec![edited], removed);
+        assert_eq!(vec![moved], added);
+
+        // two guards on the same patrol are two guards
+        let (removed, added) = diff_guards(&[kept.clone()], &[kept.clone(), kept.clone()]);
+        assert!(removed.is_empty());
+        assert_eq!(vec![kept], added);
+    }
+}
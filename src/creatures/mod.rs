@@ -1,25 +1,38 @@
+use crate::animations_handler::blend::PoseSnapshot;
+use crate::animations_handler::graph::{AnimationGraph, AnimationParameters};
 use crate::animations_handler::{
     AddAnimation, AnimationEntityLink, AnimationStopWatch, ChangeAnimation, TagPlayerScene,
 };
+use crate::creatures::definition::{CreatureDefinitions, MovementSpeed};
+use crate::creatures::ground::Airborne;
 use crate::creatures::skelly::{Skelly, SkellyAnimationId};
 use bevy::math::vec3;
 
+use crate::animations_handler::VecSceneHandle;
 use crate::camera::ShiftFromPlayer;
+use crate::combat::Health;
 use crate::creatures::SceneModelState::{FullBody, HalfBody, OnlyHead};
-use crate::map::{I_SHIFT, J_SHIFT};
+use crate::input::{Action, InputActions};
+use crate::inventory::{Inventory, ItemType};
+use crate::map::grid::{grid_to_world, TileMap};
+use crate::map::level::{Level, ProgressRule};
+use crate::map::{Campaign, LevelLoad, LevelReady};
+use crate::trigger_zone::{TriggerAction, TriggerEvent};
 use crate::{directions, SceneHandle};
 use bevy::prelude::*;
 use bevy_rapier3d::dynamics::Velocity;
+use serde::{Deserialize, Serialize};
 
-mod bone_parts;
+pub(crate) mod bone_parts;
+pub(crate) mod definition;
+pub(crate) mod ground;
+pub(crate) mod guard;
 pub(crate) mod skelly;
 
 /// marker
 #[derive(Component)]
 pub(crate) struct BoneTag;
 
-pub static GLTF_PATH_FULL_BODY: &str = "models/full_body/scene.gltf";
-pub static GLTF_PATH_HALF_BODY: &str = "models/half/half_body.gltf";
 pub static GLTF_PATH_CHEST: &str = "models/chest/chest.gltf";
 pub static GLTF_PATH_HEAD: &str = "models/head/head_with_animation.gltf";
 pub static GLTF_PATH_LEG: &str = "models/leg/leg.gltf";
@@ -35,25 +48,32 @@ pub const CHEST_NEEDED_FULL_BODY: usize = 1;
 pub const ARMS_NEEDED_FULL_BODY: usize = 2;
 pub const LEGS_NEEDED_FULL_BODY: usize = 2;
 
+/// After this many seconds in the air, Skelly fell into the void and respawns on the last checkpoint
+const RESPAWN_DELAY: f32 = 1.2;
+/// Skelly respawns this high above the checkpoint and falls on it
+const RESPAWN_DROP: f32 = 2.0;
+
 pub trait CreatureTrait {
     fn spawn(
         commands: Commands,
         asset_server: Res<AssetServer>,
         event_writer: EventWriter<AddAnimation>,
+        definitions: &CreatureDefinitions,
+        position: Vec3,
     );
 
-    fn update_animation(
-        target: u32,
-        index_animation: usize,
-        event_writer: &mut EventWriter<ChangeAnimation>,
-    );
-
-    fn can_move(animation_index: usize) -> bool;
+    /// Animations played by this type of creature, and their transitions
+    fn animation_graph(type_creature: TypeCreature) -> AnimationGraph;
 }
 
 #[derive(Component)]
 pub struct ToDespawn;
 
+/// Ressource holding where Skelly respawns after falling
+/// Set to the player start of each level, then by the SetCheckpoint trigger action
+#[derive(Default)]
+pub struct Checkpoint(pub Vec3);
+
 /// Player marker
 #[derive(Component)]
 pub(crate) struct Player;
@@ -64,34 +84,140 @@ pub(crate) struct Player;
 ///   - Scene with only heads
 pub struct VecSkellyScenes(pub Vec<SceneHandle>);
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub enum SceneModelState {
     FullBody,
     HalfBody,
     OnlyHead,
 }
 
+impl SceneModelState {
+    /// number of steps from OnlyHead
+    fn rank(&self) -> usize {
+        match self {
+            OnlyHead => 0,
+            HalfBody => 1,
+            FullBody => 2,
+        }
+    }
+
+    /// Returns true if the body is `other` or more complete
+    pub fn is_at_least(&self, other: &SceneModelState) -> bool {
+        self.rank() >= other.rank()
+    }
+
+    /// Health of Skelly in this state, each hit knocks him down one state
+    pub fn health(&self) -> u32 {
+        self.rank() as u32 + 1
+    }
+
+    /// State of Skelly with this much health left, he keeps his head at 0
+    pub fn from_health(health: u32) -> Self {
+        match health {
+            0 | 1 => OnlyHead,
+            2 => HalfBody,
+            _ => FullBody,
+        }
+    }
+
+    fn lower(&self) -> Self {
+        match self {
+            FullBody => HalfBody,
+            HalfBody | OnlyHead => OnlyHead,
+        }
+    }
+
+    /// Items lost when knocked down from this state to the lower one
+    /// Every arm, or every leg and chest, goes: Skelly has to find them again to upgrade
+    fn parts_lost(&self, inventory: &Inventory) -> Vec<(ItemType, usize)> {
+        match self {
+            FullBody => vec![
+                (ItemType::Arm, inventory.count(ItemType::Arm)),
+                (
+                    ItemType::Bone,
+                    BONES_NEEDED_FULL_BODY - BONES_NEEDED_HALF_BODY,
+                ),
+            ],
+            HalfBody => vec![
+                (ItemType::Leg, inventory.count(ItemType::Leg)),
+                (ItemType::Chest, inventory.count(ItemType::Chest)),
+                (ItemType::Bone, BONES_NEEDED_HALF_BODY),
+            ],
+            OnlyHead => vec![],
+        }
+    }
+}
+
 pub struct CreaturePlugin;
 impl Plugin for CreaturePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(bone_parts::BonePlugin)
+            .add_plugin(definition::DefinitionPlugin)
+            .add_plugin(ground::GroundPlugin)
+            .add_plugin(guard::GuardPlugin)
             .add_state(OnlyHead)
-            .add_system_set(SystemSet::on_exit(OnlyHead).with_system(update_player_model))
-            .add_system_set(SystemSet::on_exit(FullBody).with_system(update_player_model))
-            .add_system_set(SystemSet::on_exit(HalfBody).with_system(update_player_model))
-            .add_startup_system(spawn_skelly)
+            .init_resource::<Checkpoint>()
+            .add_system_set(SystemSet::on_enter(OnlyHead).with_system(update_player_model))
+            .add_system_set(SystemSet::on_enter(FullBody).with_system(update_player_model))
+            .add_system_set(SystemSet::on_enter(HalfBody).with_system(update_player_model))
+            .add_system(spawn_skelly)
             .add_system(keyboard_control)
+            .add_system(set_checkpoint_on_trigger)
+            .add_system(lose_parts_on_damage)
             .add_system_to_stage(CoreStage::First, check_falling_player)
             .add_system(cleanup_creature);
     }
 }
 
+/// Spawns Skelly on the player start of the first loaded level
+/// On the next levels, Skelly is moved to the player start and,
+/// if the level's ProgressRule is Reset, loses his body and his inventory
+/// When the level is reloaded, Skelly stays as he is
 fn spawn_skelly(
     command: Commands,
     asset_server: Res<AssetServer>,
     event_writer: EventWriter<AddAnimation>,
+    definitions: Res<CreatureDefinitions>,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut query_player: Query<(&mut Transform, &mut Velocity, &mut Inventory), With<Player>>,
+    mut scene_state: ResMut<State<SceneModelState>>,
+    mut checkpoint: ResMut<Checkpoint>,
 ) {
-    Skelly::spawn(command, asset_server, event_writer);
+    if let Some((level, load)) = level_events
+        .iter()
+        .last()
+        .and_then(|event| levels.get(&event.0).map(|level| (level, event.1)))
+    {
+        let starting_position = grid_to_world(level.player_start.0, level.player_start.1);
+
+        if let Ok((mut transform, mut velocity, mut inventory)) = query_player.get_single_mut() {
+            if load == LevelLoad::Reload {
+                return;
+            }
+
+            checkpoint.0 = starting_position;
+            transform.translation = starting_position;
+            velocity.linvel = Vec3::ZERO;
+
+            if level.progress == ProgressRule::Reset {
+                *inventory = Inventory::default();
+                if *scene_state.current() != OnlyHead {
+                    scene_state.set(OnlyHead).unwrap();
+                }
+            }
+            return;
+        }
+
+        checkpoint.0 = starting_position;
+        Skelly::spawn(
+            command,
+            asset_server,
+            event_writer,
+            &definitions,
+            starting_position,
+        );
+    }
 }
 
 //#[derive(Bundle, Clone)]
@@ -119,7 +245,7 @@ impl CurrentAnimationIndex {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Copy, Deserialize, Serialize)]
 pub enum TypeCreature {
     SkellyFullBody,
     SkellyOnlyHead,
@@ -129,6 +255,8 @@ pub enum TypeCreature {
     Leg,
     Bone,
     Arm,
+    /// hostile skeleton, patrolling and chasing Skelly
+    Guard,
 }
 
 //#[derive(Bundle)]
@@ -142,41 +270,58 @@ pub struct Creature {
     pub can_move: bool,
 }
 
-impl Creature {
-    pub fn update_animation(
-        &self,
-        target: u32,
-        index_animation: usize,
-        event_writer: &mut EventWriter<ChangeAnimation>,
-    ) {
-        match self.type_creature {
-            TypeCreature::SkellyFullBody
-            | TypeCreature::SkellyHalf
-            | TypeCreature::SkellyOnlyHead => {
-                Skelly::update_animation(target, index_animation, event_writer);
-            }
-            _ => {
-                event_writer.send(ChangeAnimation {
-                    target,
-                    index: 0,
-                    repeat: true,
-                });
-            }
-        }
-    }
-}
-
+/// Respawns Skelly on the last checkpoint with the Spawn animation if he keeps falling,
+/// his AnimationGraph plays Fall until then
+/// Respawning costs the items of the level's respawn_cost, if any
+/// Skelly also respawns when he has no health left
 fn check_falling_player(
-    mut player_query: Query<(&mut Transform, &mut Velocity), With<Player>>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Velocity,
+            &mut Inventory,
+            &mut Health,
+            Option<&mut Airborne>,
+        ),
+        With<Player>,
+    >,
+    scene_state: Res<State<SceneModelState>>,
     shift_value: Res<ShiftFromPlayer>,
     mut query_camera: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
+    checkpoint: Res<Checkpoint>,
+    campaign: Res<Campaign>,
+    levels: Res<Assets<Level>>,
+    event_writer: EventWriter<ChangeAnimation>,
 ) {
-    if let Ok((mut player_transform, mut velocity)) = player_query.get_single_mut() {
-        if player_transform.translation.y < -2.0 {
-            info!("Falling");
-            let starting_position = 7.0 * I_SHIFT + 7.0 * J_SHIFT;
-            player_transform.translation = Vec3::new(starting_position.x, 2.0, starting_position.z);
+    if let Ok((
+        entity,
+        mut player_transform,
+        mut velocity,
+        mut inventory,
+        mut health,
+        mut airborne,
+    )) = player_query.get_single_mut()
+    {
+        let seconds_in_air = airborne.as_ref().map_or(0.0, |airborne| airborne.seconds);
+
+        if seconds_in_air >= RESPAWN_DELAY || health.is_dead() {
+            info!("Respawning on {}", checkpoint.0);
+            player_transform.translation = checkpoint.0 + Vec3::Y * RESPAWN_DROP;
             velocity.linvel = Vec3::ZERO;
+            if let Some(airborne) = airborne.as_mut() {
+                airborne.seconds = 0.0;
+            }
+            health.current = scene_state.current().health();
+
+            if let Some(cost) = levels
+                .get(campaign.current_level())
+                .and_then(|level| level.respawn_cost)
+            {
+                let lost = inventory.remove(cost.item, cost.count);
+                info!("Lost {} {}", lost, cost.item.get_name(lost));
+            }
+
             if let Ok(mut camera_transform) = query_camera.get_single_mut() {
                 let shift = shift_value.0;
                 *camera_transform = Transform::from_xyz(
@@ -186,10 +331,71 @@ fn check_falling_player(
                 )
                 .looking_at(player_transform.translation, Vec3::Y);
             }
+
+            send_new_animation(
+                entity.id(),
+                SkellyAnimationId::Spawn as usize,
+                false,
+                event_writer,
+            );
+        }
+    }
+}
+
+/// Handles the SetCheckpoint action of the trigger zones
+fn set_checkpoint_on_trigger(
+    mut trigger_events: EventReader<TriggerEvent>,
+    mut checkpoint: ResMut<Checkpoint>,
+) {
+    for TriggerEvent(action) in trigger_events.iter() {
+        if let TriggerAction::SetCheckpoint(position) = action {
+            checkpoint.0 = *position;
         }
     }
 }
 
+/// Knocks Skelly down one body state per health point lost
+/// The parts of the lost states are taken out of the inventory and scattered around him
+fn lose_parts_on_damage(
+    mut commands: Commands,
+    vec_scene_handlers: Res<VecSceneHandle>,
+    tile_map: Res<TileMap>,
+    mut query_player: Query<(&Transform, &Health, &mut Inventory), (With<Player>, Changed<Health>)>,
+    mut scene_state: ResMut<State<SceneModelState>>,
+) {
+    if let Ok((transform, health, mut inventory)) = query_player.get_single_mut() {
+        let target = SceneModelState::from_health(health.current);
+        let mut state = scene_state.current().clone();
+        if target.is_at_least(&state) {
+            return;
+        }
+
+        let mut parts = Vec::new();
+        while state != target {
+            for (item, count) in state.parts_lost(&inventory) {
+                let removed = inventory.remove(item, count);
+                if let Some(part) = item.part() {
+                    parts.extend(std::iter::repeat(part).take(removed));
+                }
+            }
+            state = state.lower();
+        }
+
+        info!("Hurt, {:?} losing {} parts", target, parts.len());
+        bone_parts::scatter_parts(
+            &mut commands,
+            &vec_scene_handlers,
+            &tile_map,
+            transform.translation,
+            &parts,
+        );
+        if let Err(error) = scene_state.set(target) {
+            warn!("Can not knock Skelly down: {:?}", error);
+        }
+    }
+}
+
+/// Cuts to the animation, without cross-fade
 fn send_new_animation(
     target_entity: u32,
     animation_index: usize,
@@ -200,72 +406,87 @@ fn send_new_animation(
         target: target_entity,
         index: animation_index,
         repeat: do_repeat,
+        blend: 0.0,
     });
 }
 
+/// Moves Skelly with the Move actions or the left stick, the Run action makes him run
+/// The keys are reset while he falls, so that he does not keep walking after respawning
 fn keyboard_control(
-    event_writer: EventWriter<ChangeAnimation>,
+    input_actions: Res<InputActions>,
     mut keyboard_input: ResMut<Input<KeyCode>>,
-    mut query_player: Query<(Entity, &mut Transform, &mut Velocity, &mut Creature), With<Player>>,
+    mut query_player: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut Creature,
+            &MovementSpeed,
+            &mut AnimationParameters,
+            Option<&Airborne>,
+        ),
+        With<Player>,
+    >,
 ) {
     let mut vector_direction = Vec3::ZERO;
     let mut is_shift = 0.0;
 
-    if keyboard_input.pressed(KeyCode::Z) || keyboard_input.pressed(KeyCode::W) {
+    if input_actions.pressed(Action::MoveUp) {
         vector_direction += Vec3::new(1.0, 0.0, 1.0);
     }
 
-    if keyboard_input.pressed(KeyCode::D) {
+    if input_actions.pressed(Action::MoveRight) {
         vector_direction += Vec3::new(-1.0, 0.0, 1.0);
     }
 
-    if keyboard_input.pressed(KeyCode::S) {
+    if input_actions.pressed(Action::MoveDown) {
         vector_direction += Vec3::new(-1.0, 0.0, -1.0);
     }
 
-    if keyboard_input.pressed(KeyCode::Q) || keyboard_input.pressed(KeyCode::A) {
+    if input_actions.pressed(Action::MoveLeft) {
         vector_direction += Vec3::new(1.0, 0.0, -1.0);
     }
 
-    if keyboard_input.pressed(KeyCode::LShift) {
+    // screen up and screen right, like the MoveUp and MoveRight actions
+    let stick = input_actions.movement();
+    vector_direction += Vec3::new(1.0, 0.0, 1.0) * stick.y + Vec3::new(-1.0, 0.0, 1.0) * stick.x;
+
+    if input_actions.pressed(Action::Run) {
         is_shift = 1.0;
     }
 
-    if let Ok((entity, mut player_transform, mut player_velocity, mut player_creature)) =
-        query_player.get_single_mut()
+    if let Ok((
+        mut player_transform,
+        mut player_velocity,
+        mut player_creature,
+        speed,
+        mut parameters,
+        airborne,
+    )) = query_player.get_single_mut()
     {
-        if player_transform.translation.y < -2.0 || player_transform.translation.y > 1.0 {
+        if airborne.map_or(false, Airborne::is_falling) {
             keyboard_input.reset_all();
+            parameters.speed = 0.0;
             return;
         }
 
-        if player_creature.type_creature == TypeCreature::SkellyOnlyHead {
-            is_shift = 0.0;
-        }
-
-        let idle_index = SkellyAnimationId::Idle as usize;
-
         // Returns if vector_direction is 0
         if vector_direction == Vec3::ZERO {
-            if player_creature.current_animation_index == SkellyAnimationId::Walk
-                || player_creature.current_animation_index == SkellyAnimationId::Run
-            {
-                send_new_animation(entity.id(), idle_index, true, event_writer);
-            }
-
+            parameters.speed = 0.0;
             player_velocity.linvel = vec3(0.0, player_velocity.linvel.y, 0.0);
             return;
         }
 
+        // the AnimationGraph walks or runs once the current animation allows it
+        parameters.running = is_shift >= 1.0;
+        parameters.speed = if parameters.running {
+            speed.run
+        } else {
+            speed.walk
+        };
+
         // Returns if the player can not move
-        match player_creature.type_creature {
-            TypeCreature::SkellyFullBody | TypeCreature::SkellyHalf => {
-                if !Skelly::can_move(player_creature.current_animation_index.0) {
-                    return;
-                }
-            }
-            TypeCreature::SkellyOnlyHead => {}
-            _ => return,
+        if !player_creature.can_move {
+            return;
         }
 
         // Update Transform.translation
@@ -273,10 +494,11 @@ fn keyboard_control(
         player_creature.direction_vec3 = translation;
         translation.y = player_velocity.linvel.y;
 
-        player_velocity.linvel = translation * 2.0 * (1.0 + (is_shift * 2.0));
+        player_velocity.linvel = translation * parameters.speed;
 
         // Update rotation
-        let direction = directions::map_vec3_to_direction(vector_direction).unwrap();
+        let direction = directions::nearest_direction(vector_direction);
+        player_creature.direction = direction;
         let qu = Quat::from_rotation_y(direction.get_angle());
         //let rotation = if player_transform.rotation.angle_between(qu).abs() > 3.0 {
         //    qu
@@ -285,45 +507,32 @@ fn keyboard_control(
         //};
         let rotation = player_transform.rotation.lerp(qu, 0.1);
         player_transform.rotation = rotation;
-
-        // no running animation when only head
-        if player_creature.type_creature == TypeCreature::SkellyOnlyHead {
-            return;
-        }
-
-        let moving_animation = if is_shift >= 1.0 {
-            SkellyAnimationId::Run as usize
-        } else {
-            SkellyAnimationId::Walk as usize
-        };
-
-        // no need to update animation
-        if moving_animation as usize == player_creature.current_animation_index.0 {
-            return;
-        }
-
-        // do we need to update animation? depends it was already walking/running and if pressing shift
-        // I think this can be easily less complicated, but no time
-        if (player_creature.current_animation_index.0 != SkellyAnimationId::Run as usize
-            && player_creature.current_animation_index.0 != SkellyAnimationId::Walk as usize)
-            || (player_creature.current_animation_index.0 == SkellyAnimationId::Run as usize
-                && moving_animation == SkellyAnimationId::Walk as usize)
-            || (player_creature.current_animation_index.0 == SkellyAnimationId::Walk as usize
-                && moving_animation == SkellyAnimationId::Run as usize)
-        {
-            send_new_animation(entity.id(), moving_animation, true, event_writer);
-        }
     }
 }
 
 fn update_player_model(
     mut command: Commands,
-    vec_scenes: Res<VecSkellyScenes>,
+    vec_scenes: Option<Res<VecSkellyScenes>>,
+    definitions: Res<CreatureDefinitions>,
     scene_state: Res<State<SceneModelState>>,
     mut query_child_scene: Query<Entity, With<TagPlayerScene>>,
     mut query_player: Query<(Entity, &AnimationEntityLink, &mut Creature), With<Player>>,
+    mut query_health: Query<&mut Health, With<Player>>,
     mut query_stopwatch: Query<&mut AnimationStopWatch>,
+    query_children: Query<&Children>,
+    query_bones: Query<(&Name, &Transform)>,
 ) {
+    // the initial state is entered before Skelly is spawned
+    let vec_scenes = match vec_scenes {
+        Some(vec_scenes) => vec_scenes,
+        None => return,
+    };
+
+    // each body state has its own health, upgrading heals Skelly
+    if let Ok(mut health) = query_health.get_single_mut() {
+        health.current = scene_state.current().health();
+    }
+
     if let Ok(child_scene) = query_child_scene.get_single_mut() {
         if let Ok((player_entity, animation_player, mut creature)) = query_player.get_single_mut() {
             info!("Child found {:?}", child_scene);
@@ -331,6 +540,8 @@ fn update_player_model(
             // remove current:,
             info!("Skelly : {:?}", player_entity);
             info!("Despawning: {:?} / {:?}", child_scene, animation_player.0);
+            // the new body fades from the pose of this one
+            let snapshot = PoseSnapshot::take(animation_player.0, &query_children, &query_bones);
             command
                 .entity(player_entity)
                 .remove_children(&[child_scene]);
@@ -340,76 +551,38 @@ fn update_player_model(
                 .entity(player_entity)
                 .remove::<AnimationEntityLink>();
 
-            info!("Calling update on_exit: {:?}", *scene_state.current());
-
-            let scene_full_body = &vec_scenes.0[0];
-            let scene_half = &vec_scenes.0[1];
-            let scene_head = &vec_scenes.0[2];
-
-            let mut index_animation = SkellyAnimationId::None as usize;
-
-            match scene_state.current() {
-                OnlyHead => {
-                    // Désactive HEAD, active HALF
-
-                    creature.type_creature = TypeCreature::SkellyHalf;
-
-                    // add new
-                    command.entity(player_entity).with_children(|parent| {
-                        parent
-                            .spawn_bundle(SceneBundle {
-                                scene: scene_half.handle.clone(),
-                                transform: Transform {
-                                    translation: Default::default(),
-                                    rotation: Default::default(),
-                                    scale: Vec3::ONE * 0.6,
-                                },
-                                ..default()
-                            })
-                            .insert(TagPlayerScene);
-                    });
-                }
-                FullBody => {
-                    // Désactive FULL_BODY, active HEAD
-                    creature.type_creature = TypeCreature::SkellyOnlyHead;
-
-                    index_animation = SkellyAnimationId::Idle as usize;
-
-                    // add new
-                    command.entity(player_entity).with_children(|parent| {
-                        parent
-                            .spawn_bundle(SceneBundle {
-                                scene: scene_head.handle.clone(),
-                                transform: Transform {
-                                    translation: Vec3::new(0.0, -0.5, 0.0),
-                                    rotation: Quat::from_scaled_axis(Vec3::new(0.0, 0.0, 0.0)),
-                                    scale: Vec3::ONE * 0.6,
-                                },
-                                ..default()
-                            })
-                            .insert(TagPlayerScene);
-                    });
-                }
-                HalfBody => {
-                    // DESACTIVATE HALF, ACTIVATE FULL
-                    creature.type_creature = TypeCreature::SkellyFullBody;
-
-                    // add new
-                    command.entity(player_entity).with_children(|parent| {
-                        parent
-                            .spawn_bundle(SceneBundle {
-                                scene: scene_full_body.handle.clone(),
-                                transform: Transform {
-                                    translation: Default::default(),
-                                    rotation: Default::default(),
-                                    scale: Vec3::ONE * 0.6,
-                                },
-                                ..default()
-                            })
-                            .insert(TagPlayerScene);
-                    });
-                }
-            }
+            info!("Calling update on_enter: {:?}", *scene_state.current());
+
+            let (type_creature, scene) = match scene_state.current() {
+                FullBody => (TypeCreature::SkellyFullBody, &vec_scenes.0[0]),
+                HalfBody => (TypeCreature::SkellyHalf, &vec_scenes.0[1]),
+                OnlyHead => (TypeCreature::SkellyOnlyHead, &vec_scenes.0[2]),
+            };
+            let definition = definitions.get(type_creature);
+
+            // the head keeps rolling, the bodies play Spawn
+            let index_animation = if type_creature == TypeCreature::SkellyOnlyHead {
+                SkellyAnimationId::Idle as usize
+            } else {
+                SkellyAnimationId::None as usize
+            };
+
+            creature.type_creature = type_creature;
+            creature.can_move = definition.can_move(SkellyAnimationId::from(index_animation));
+            command
+                .entity(player_entity)
+                .insert(definition.speed())
+                .insert(Skelly::animation_graph(type_creature))
+                .insert(snapshot)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(SceneBundle {
+                            scene: scene.handle.clone(),
+                            transform: definition.scene_transform.transform(),
+                            ..default()
+                        })
+                        .insert(TagPlayerScene);
+                });
             creature.current_animation_index.0 = index_animation;
             for mut stopwatch in query_stopwatch.iter_mut() {
                 if stopwatch.creature_entity_id == player_entity.id() {
@@ -438,3 +611,44 @@ fn cleanup_creature(
         commands.entity(e).despawn_recursive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_of_body_states() {
+        for state in [OnlyHead, HalfBody, FullBody] {
+            assert_eq!(state, SceneModelState::from_health(state.health()));
+        }
+        assert_eq!(OnlyHead, SceneModelState::from_health(0));
+        assert_eq!(
+            HalfBody,
+            SceneModelState::from_health(FullBody.health() - 1)
+        );
+    }
+
+    #[test]
+    fn test_parts_lost_prevent_upgrade() {
+        let mut inventory = Inventory::default();
+        inventory.add_bone(BONES_NEEDED_FULL_BODY);
+        inventory.add_arms(ARMS_NEEDED_FULL_BODY);
+        inventory.add_legs(LEGS_NEEDED_FULL_BODY);
+        inventory.add_chest(CHEST_NEEDED_FULL_BODY);
+
+        for (item, count) in FullBody.parts_lost(&inventory) {
+            inventory.remove(item, count);
+        }
+        assert_eq!(0, inventory.count(ItemType::Arm));
+        assert_eq!(BONES_NEEDED_HALF_BODY, inventory.count(ItemType::Bone));
+        assert_eq!(LEGS_NEEDED_FULL_BODY, inventory.count(ItemType::Leg));
+
+        for (item, count) in HalfBody.parts_lost(&inventory) {
+            inventory.remove(item, count);
+        }
+        for item in [ItemType::Bone, ItemType::Leg, ItemType::Chest] {
+            assert_eq!(0, inventory.count(item));
+        }
+        assert!(OnlyHead.parts_lost(&inventory).is_empty());
+    }
+}
@@ -1,30 +1,33 @@
-use crate::animations_handler::{
-    AddAnimation, ChangeAnimation, HashMapAnimationClip, SceneHandle, TagPlayerScene,
-};
+use crate::animations_handler::graph::{AnimationGraph, AnimationParameters, Condition};
+use crate::animations_handler::{AddAnimation, TagPlayerScene};
+use crate::combat::Health;
+use crate::creatures::definition::CreatureDefinitions;
+use crate::creatures::ground::Airborne;
 use crate::creatures::{
-    Creature, CreatureTrait, CurrentAnimationIndex, Player, TypeCreature, VecSkellyScenes,
-    GLTF_PATH_FULL_BODY, GLTF_PATH_HALF_BODY, GLTF_PATH_HEAD,
+    Creature, CreatureTrait, CurrentAnimationIndex, Player, SceneModelState, TypeCreature,
+    VecSkellyScenes,
 };
 use crate::directions;
 use crate::inventory::Inventory;
-use crate::map::{I_SHIFT, J_SHIFT};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+/// Animations played by the skeletons, their clips are given by the creature definitions
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub(crate) enum SkellyAnimationId {
-    Spawn,         // ?
-    Idle,          // duration: 1.5800002
-    LookingAround, // duration: 3.1800003
-    Attack,        // duration: 2.3200002
-    Yell,          // duration: 1.5800002
-    Walk,          // duration: 0.9800001
-    Run,           // duration: 0.78000003
-    Fall,          // ?
-    Hit,           // ?
-    Die,           // ?
-    Hanged,        // ?
-    None,          // ?
+    Spawn,
+    Idle,
+    LookingAround,
+    Attack,
+    Yell,
+    Walk,
+    Run,
+    Fall,
+    Hit,
+    Die,
+    Hanged,
+    None,
 }
 
 impl From<usize> for SkellyAnimationId {
@@ -65,18 +68,6 @@ impl From<usize> for SkellyAnimationId {
     }
 }*/
 
-const SKELLY_ANIM_DURATION_SPAWN: f32 = 1.30;
-const SKELLY_ANIM_DURATION_IDLE: f32 = 1.58;
-const SKELLY_ANIM_DURATION_LOOKING_AROUND: f32 = 3.18;
-const SKELLY_ANIM_DURATION_ATTACK: f32 = 2.32;
-const SKELLY_ANIM_DURATION_YELL: f32 = 1.58;
-const SKELLY_ANIM_DURATION_WALK: f32 = 0.98;
-const SKELLY_ANIM_DURATION_RUN: f32 = 0.78;
-const SKELLY_ANIM_DURATION_FALL: f32 = 1.1;
-const SKELLY_ANIM_DURATION_HIT: f32 = 0.62;
-const SKELLY_ANIM_DURATION_DIE: f32 = 1.06;
-const SKELLY_ANIM_DURATION_HANGED: f32 = 1.58;
-
 /*impl Into<CurrentAnimationIndex> for SkellyAnimationId {
     fn into(self) -> CurrentAnimationIndex {
         CurrentAnimationIndex(self)
@@ -90,46 +81,24 @@ impl From<SkellyAnimationId> for CurrentAnimationIndex {
     }
 }
 
-impl SkellyAnimationId {
-    pub(crate) fn get_duration(&self) -> f32 {
-        match self {
-            SkellyAnimationId::Idle => SKELLY_ANIM_DURATION_IDLE,
-            SkellyAnimationId::LookingAround => SKELLY_ANIM_DURATION_LOOKING_AROUND,
-            SkellyAnimationId::Attack => SKELLY_ANIM_DURATION_ATTACK,
-            SkellyAnimationId::Yell => SKELLY_ANIM_DURATION_YELL,
-            SkellyAnimationId::Walk => SKELLY_ANIM_DURATION_WALK,
-            SkellyAnimationId::Run => SKELLY_ANIM_DURATION_RUN,
-            SkellyAnimationId::Fall => SKELLY_ANIM_DURATION_FALL,
-            SkellyAnimationId::Hit => SKELLY_ANIM_DURATION_HIT,
-            SkellyAnimationId::Die => SKELLY_ANIM_DURATION_DIE,
-            SkellyAnimationId::Spawn => SKELLY_ANIM_DURATION_SPAWN,
-            SkellyAnimationId::Hanged => SKELLY_ANIM_DURATION_HANGED,
-            SkellyAnimationId::None => 0.0,
-        }
-    }
-}
-
 pub(crate) struct Skelly;
 impl CreatureTrait for Skelly {
+    /// Skelly spawns as a head, the scenes of the two other bodies are loaded too
     fn spawn(
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         mut event_writer: EventWriter<AddAnimation>,
+        definitions: &CreatureDefinitions,
+        starting_position: Vec3,
     ) {
-        let i_shift = I_SHIFT;
-        let j_shift = J_SHIFT;
-        let starting_position = 7.0 * i_shift + 7.0 * j_shift;
-
-        // let mut skelly_scene_handle = setup_skelly(&asset_server, "models/skeleton/scene.gltf");
-        let mut full_body_scene_handle = setup_skelly(
-            &asset_server,
-            GLTF_PATH_FULL_BODY,
-            TypeCreature::SkellyFullBody,
-        );
-        let mut half_scene_handle =
-            setup_skelly(&asset_server, GLTF_PATH_HALF_BODY, TypeCreature::SkellyHalf);
-        let mut head_scene_handle =
-            setup_skelly(&asset_server, GLTF_PATH_HEAD, TypeCreature::SkellyOnlyHead);
+        let head = definitions.get(TypeCreature::SkellyOnlyHead);
+        let mut full_body_scene_handle = definitions
+            .get(TypeCreature::SkellyFullBody)
+            .scene_handle(&asset_server, TypeCreature::SkellyFullBody);
+        let mut half_scene_handle = definitions
+            .get(TypeCreature::SkellyHalf)
+            .scene_handle(&asset_server, TypeCreature::SkellyHalf);
+        let mut head_scene_handle = head.scene_handle(&asset_server, TypeCreature::SkellyOnlyHead);
 
         // Skeleton
         let skelly_id = commands
@@ -142,28 +111,11 @@ impl CreatureTrait for Skelly {
                 },
                 ..default()
             })
-            //.with_children(|parent| {
-            //    parent
-            //        .spawn_bundle(SceneBundle {
-            //            scene: full_body_scene_handle.handle.clone(),
-            //            transform: Transform {
-            //                translation: Default::default(),
-            //                rotation: Default::default(),
-            //                scale: Vec3::ONE * 0.6,
-            //            },
-            //            ..default()
-            //        })
-            //        .insert(TagPlayerScene);
-            // })
             .with_children(|parent| {
                 parent
                     .spawn_bundle(SceneBundle {
                         scene: head_scene_handle.handle.clone(),
-                        transform: Transform {
-                            translation: Vec3::new(0.0, -0.5, 0.0),
-                            rotation: Quat::from_scaled_axis(Vec3::new(0.0, 0.0, 0.0)),
-                            scale: Vec3::ONE * 0.6,
-                        },
+                        transform: head.scene_transform.transform(),
                         ..default()
                     })
                     .insert(TagPlayerScene);
@@ -171,11 +123,11 @@ impl CreatureTrait for Skelly {
             .with_children(|children| {
                 children
                     .spawn()
-                    .insert(Collider::cuboid(0.3, 0.9, 0.3))
+                    .insert(head.collider.collider())
                     .insert(ActiveEvents::COLLISION_EVENTS)
                     .insert_bundle(PbrBundle {
                         transform: Transform {
-                            translation: Vec3::new(0.0, 1.0, 0.3),
+                            translation: head.collider.offset(),
                             rotation: Quat::from_rotation_y(
                                 directions::Direction::Left.get_angle(),
                             ), // Direction::Left
@@ -202,9 +154,17 @@ impl CreatureTrait for Skelly {
                 current_animation_index: CurrentAnimationIndex::from(
                     SkellyAnimationId::Idle as usize,
                 ),
-                can_move: false,
+                can_move: head.can_move(SkellyAnimationId::Idle),
             })
+            .insert(head.speed())
+            .insert(Skelly::animation_graph(TypeCreature::SkellyOnlyHead))
+            .insert(AnimationParameters::default())
             .insert(Player)
+            .insert(Airborne::default())
+            .insert(Health {
+                current: SceneModelState::OnlyHead.health(),
+                max: SceneModelState::FullBody.health(),
+            })
             .insert(Inventory::default())
             .insert(Name::new("Skelly"))
             .id();
@@ -241,53 +201,62 @@ impl CreatureTrait for Skelly {
         ]));
     }
 
-    fn update_animation(
-        target: u32,
-        index_animation: usize,
-        event_writer: &mut EventWriter<ChangeAnimation>,
-    ) {
-        let mut new_animation = SkellyAnimationId::Idle;
-        let mut repeat = false;
+    /// The bodies walk and run, the head only rolls
+    /// Idle, Walk and Run loop, the other animations go back to Idle when they are over
+    fn animation_graph(type_creature: TypeCreature) -> AnimationGraph {
+        use Condition::*;
+        use SkellyAnimationId::{
+            Attack, Die, Fall, Hanged, Hit, Idle, LookingAround, Run, Spawn, Walk, Yell,
+        };
 
-        match SkellyAnimationId::from(index_animation) {
-            SkellyAnimationId::Idle => return,
-            SkellyAnimationId::LookingAround => {
-                new_animation = SkellyAnimationId::Idle;
-                repeat = true;
-            }
-            SkellyAnimationId::Attack => {}
-            SkellyAnimationId::Yell => {}
-            SkellyAnimationId::Walk => return,
-            SkellyAnimationId::Run => return,
-            SkellyAnimationId::Fall => {}
-            SkellyAnimationId::Hit => {}
-            SkellyAnimationId::Die => {}
-            SkellyAnimationId::Spawn => {
-                new_animation = SkellyAnimationId::LookingAround;
-                repeat = false;
-            }
-            SkellyAnimationId::Hanged => {}
-            SkellyAnimationId::None => {
-                new_animation = SkellyAnimationId::Spawn;
-                repeat = false;
-            }
+        let mut graph = AnimationGraph::default()
+            .with_state(
+                SkellyAnimationId::None as usize,
+                false,
+                &[(Finished, Spawn as usize)],
+            )
+            .with_state(Spawn as usize, false, &[(Finished, LookingAround as usize)])
+            .with_state(LookingAround as usize, false, &[(Finished, Idle as usize)])
+            .with_state(Fall as usize, false, &[(Grounded, Idle as usize)]);
+        for animation in [Attack, Yell, Hit, Die, Hanged] {
+            graph = graph.with_state(animation as usize, false, &[(Finished, Idle as usize)]);
         }
 
-        event_writer.send(ChangeAnimation {
-            target,
-            index: new_animation as usize,
-            repeat,
-        });
-    }
-
-    /// Returns true is animation is Idle / Walk / Run
-    fn can_move(animation_index: usize) -> bool {
-        matches! {
-            SkellyAnimationId::from(animation_index),
-            SkellyAnimationId::Idle
-            | SkellyAnimationId::Walk
-            | SkellyAnimationId::Run
+        if type_creature == TypeCreature::SkellyOnlyHead {
+            return graph.with_state(Idle as usize, true, &[(Falling, Fall as usize)]);
         }
+
+        graph
+            .with_state(
+                Idle as usize,
+                true,
+                &[
+                    (Falling, Fall as usize),
+                    (Attacking, Attack as usize),
+                    (Running, Run as usize),
+                    (Walking, Walk as usize),
+                ],
+            )
+            .with_state(
+                Walk as usize,
+                true,
+                &[
+                    (Falling, Fall as usize),
+                    (Attacking, Attack as usize),
+                    (Still, Idle as usize),
+                    (Running, Run as usize),
+                ],
+            )
+            .with_state(
+                Run as usize,
+                true,
+                &[
+                    (Falling, Fall as usize),
+                    (Attacking, Attack as usize),
+                    (Still, Idle as usize),
+                    (Walking, Walk as usize),
+                ],
+            )
     }
 }
 
@@ -296,55 +265,48 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_can_move() {
-        let mut creature = Creature {
-            type_creature: TypeCreature::SkellyFullBody,
-            direction: directions::Direction::Up,
-            direction_vec3: Default::default(),
-            current_animation_index: CurrentAnimationIndex(SkellyAnimationId::Idle as usize),
-            can_move: false,
-        };
+    fn test_body_graph() {
+        let graph = Skelly::animation_graph(TypeCreature::SkellyFullBody);
+        let mut parameters = AnimationParameters::default();
+        let idle = SkellyAnimationId::Idle as usize;
 
+        let spawn = graph.next(SkellyAnimationId::Spawn as usize, &parameters, true);
         assert_eq!(
-            true,
-            Skelly::can_move(creature.current_animation_index.0 as usize)
+            Some((SkellyAnimationId::LookingAround as usize, false)),
+            spawn
         );
+        assert_eq!(None, graph.next(idle, &parameters, true));
 
-        creature.current_animation_index = CurrentAnimationIndex(SkellyAnimationId::Spawn as usize);
-        assert_eq!(
-            false,
-            Skelly::can_move(creature.current_animation_index.0 as usize)
-        );
+        parameters.speed = 6.0;
+        parameters.running = true;
+        let run = SkellyAnimationId::Run as usize;
+        assert_eq!(Some((run, true)), graph.next(idle, &parameters, false));
+        parameters.running = false;
+        let walk = graph.next(run, &parameters, false);
+        assert_eq!(Some((SkellyAnimationId::Walk as usize, true)), walk);
 
-        creature.current_animation_index =
-            CurrentAnimationIndex(SkellyAnimationId::LookingAround as usize);
-        assert_eq!(
-            false,
-            Skelly::can_move(creature.current_animation_index.0 as usize)
-        );
+        parameters.grounded = false;
+        let fall = SkellyAnimationId::Fall as usize;
+        assert_eq!(Some((fall, false)), graph.next(run, &parameters, false));
+        // Fall is held until he lands
+        assert_eq!(None, graph.next(fall, &parameters, true));
+        parameters.grounded = true;
+        assert_eq!(Some((idle, true)), graph.next(fall, &parameters, false));
     }
-}
-
-fn setup_skelly(
-    asset_server: &Res<AssetServer>,
-    scene_path: &str,
-    type_creature: TypeCreature,
-) -> SceneHandle {
-    let asset_scene_handle = asset_server.load(format!("{}{}", scene_path, "#Scene0").as_str());
 
-    let mut hm_animations = HashMapAnimationClip::new();
-
-    for i in 0..11 {
-        let id = SkellyAnimationId::from(i as usize);
-        let handle = asset_server.load(format!("{}#Animation{}", scene_path, id as usize).as_str());
-        hm_animations.insert(id as usize, id.get_duration(), handle);
-    }
+    #[test]
+    fn test_head_only_rolls() {
+        let graph = Skelly::animation_graph(TypeCreature::SkellyOnlyHead);
+        let parameters = AnimationParameters {
+            speed: 6.0,
+            running: true,
+            attacking: true,
+            ..default()
+        };
 
-    SceneHandle {
-        handle: asset_scene_handle,
-        vec_animations: hm_animations,
-        creature_entity_id: None,
-        type_creature,
-        activated: true,
+        assert_eq!(
+            None,
+            graph.next(SkellyAnimationId::Idle as usize, &parameters, false)
+        );
     }
 }
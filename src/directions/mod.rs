@@ -10,7 +10,19 @@ const ENTITY_LOOKING_DOWN: f32 = -(3.0 * 45.0) * PI / 180.0;
 const ENTITY_LOOKING_DOWN_LEFT: f32 = -(4.0 * 45.0) * PI / 180.0;
 const ENTITY_LOOKING_DOWN_RIGHT: f32 = -(2.0 * 45.0) * PI / 180.0;
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+/// Every direction, clockwise from Up
+pub const DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::UpRight,
+    Direction::Right,
+    Direction::DownRight,
+    Direction::Down,
+    Direction::DownLeft,
+    Direction::Left,
+    Direction::UpLeft,
+];
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Direction {
     Up,
     UpRight,
@@ -50,25 +62,15 @@ impl Direction {
     }
 }
 
-pub fn map_vec3_to_direction(vec: Vec3) -> Result<Direction, String> {
-    match vec.x as i8 {
-        0 => match vec.z as i8 {
-            1 | 2 => Ok(Direction::UpRight),
-            -1 | -2 => Ok(Direction::DownLeft),
-            _ => Err(String::from("vector_direction not equals to 1 or 2")),
-        },
-        1 | 2 => match vec.z as i8 {
-            1 | 2 => Ok(Direction::Up),
-            -1 | -2 => Ok(Direction::Left),
-            0 => Ok(Direction::UpLeft),
-            _ => Err(String::from("vector_direction not equals to 1 or 2")),
-        },
-        -1 | -2 => match vec.z as i8 {
-            1 | 2 => Ok(Direction::Right),
-            -1 | -2 => Ok(Direction::Down),
-            0 => Ok(Direction::DownRight),
-            _ => Err(String::from("vector_direction not equals to 1 or 2")),
-        },
-        _ => Err(String::from("vector_direction not equals to 1 or 2")),
-    }
+/// Direction closest to the vector, y is ignored
+pub fn nearest_direction(vec: Vec3) -> Direction {
+    let vec = Vec3::new(vec.x, 0.0, vec.z);
+    DIRECTIONS
+        .into_iter()
+        .max_by(|a, b| {
+            let dot_a = a.get_vec3().normalize().dot(vec);
+            let dot_b = b.get_vec3().normalize().dot(vec);
+            dot_a.total_cmp(&dot_b)
+        })
+        .unwrap()
 }
@@ -0,0 +1,209 @@
+use crate::creatures::TypeCreature;
+use crate::map::gate::default_requirements;
+use crate::map::grid::GridCoord;
+use crate::map::level::{FloorRect, Level, LevelChain, LevelGate, LevelPack};
+
+/// The gate is placed on the far edge of the clicked tile along j, like in the first dungeon
+const GATE_OFFSET_J: f32 = 0.65;
+const CHAIN_OFFSET_J: f32 = 0.25;
+
+fn to_coord(position: (f32, f32)) -> GridCoord {
+    GridCoord::new(position.0.round() as i32, position.1.round() as i32)
+}
+
+/// Returns the rectangles covering every tile of `rect` except `tile`
+fn split_rect(rect: &FloorRect, tile: GridCoord) -> Vec<FloorRect> {
+    let inside =
+        (rect.min.i..=rect.max.i).contains(&tile.i) && (rect.min.j..=rect.max.j).contains(&tile.j);
+    if !inside {
+        return vec![*rect];
+    }
+
+    let mut parts = Vec::new();
+    // rows before and after the tile
+    if tile.j > rect.min.j {
+        parts.push(FloorRect {
+            min: rect.min,
+            max: GridCoord::new(rect.max.i, tile.j - 1),
+        });
+    }
+    if tile.j < rect.max.j {
+        parts.push(FloorRect {
+            min: GridCoord::new(rect.min.i, tile.j + 1),
+            max: rect.max,
+        });
+    }
+    // the row of the tile, on each side of it
+    if tile.i > rect.min.i {
+        parts.push(FloorRect {
+            min: GridCoord::new(rect.min.i, tile.j),
+            max: GridCoord::new(tile.i - 1, tile.j),
+        });
+    }
+    if tile.i < rect.max.i {
+        parts.push(FloorRect {
+            min: GridCoord::new(tile.i + 1, tile.j),
+            max: GridCoord::new(rect.max.i, tile.j),
+        });
+    }
+    parts
+}
+
+/// Returns true if the tile was not already a floor tile
+pub fn add_tile(level: &mut Level, tile: GridCoord) -> bool {
+    if level.floor_tiles().contains(&tile) {
+        return false;
+    }
+    level.tiles.push(tile);
+    true
+}
+
+/// Returns true if the tile was a floor tile
+pub fn remove_tile(level: &mut Level, tile: GridCoord) -> bool {
+    if !level.floor_tiles().contains(&tile) {
+        return false;
+    }
+    level.tiles.retain(|coord| *coord != tile);
+    level.floor = level
+        .floor
+        .iter()
+        .flat_map(|rect| split_rect(rect, tile))
+        .collect();
+    true
+}
+
+/// Puts a pack holding `items` on the tile, replacing the one already there
+pub fn put_pack(level: &mut Level, tile: GridCoord, items: &[TypeCreature]) -> bool {
+    if items.is_empty() {
+        return false;
+    }
+    // a new id, the replaced pack may have been opened
+    let id = level
+        .packs
+        .iter()
+        .map(|pack| pack.id + 1)
+        .max()
+        .unwrap_or(0);
+    remove_pack(level, tile);
+    level.packs.push(LevelPack {
+        id,
+        position: (tile.i as f32, tile.j as f32),
+        items: items.to_vec(),
+        keys: vec![],
+    });
+    true
+}
+
+/// Returns true if there was a pack on the tile
+pub fn remove_pack(level: &mut Level, tile: GridCoord) -> bool {
+    let count = level.packs.len();
+    level.packs.retain(|pack| to_coord(pack.position) != tile);
+    count != level.packs.len()
+}
+
+/// Moves the first gate on the edge of the tile, or creates it
+pub fn move_gate(level: &mut Level, tile: GridCoord) -> bool {
+    let position = (tile.i as f32, tile.j as f32 + GATE_OFFSET_J);
+    match level.gates.first_mut() {
+        Some(gate) => gate.position = position,
+        None => level.gates.push(LevelGate {
+            id: 0,
+            position,
+            requirements: default_requirements(),
+        }),
+    }
+    true
+}
+
+/// Moves the first chain on the tile, or creates it for the first gate
+pub fn move_chain(level: &mut Level, tile: GridCoord) -> bool {
+    let position = (tile.i as f32, tile.j as f32 + CHAIN_OFFSET_J);
+    let gate = level.gates.first().map_or(0, |gate| gate.id);
+    match level.chains.first_mut() {
+        Some(chain) => chain.position = position,
+        None => level.chains.push(LevelChain { gate, position }),
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::level::ProgressRule;
+
+    fn square_level() -> Level {
+        Level {
+            progress: ProgressRule::CarryOver,
+            floor: vec![FloorRect {
+                min: GridCoord::new(0, 0),
+                max: GridCoord::new(2, 2),
+            }],
+            tiles: vec![],
+            player_start: (0.0, 0.0),
+            packs: vec![],
+            gates: vec![],
+            chains: vec![],
+            checkpoints: vec![],
+            respawn_cost: None,
+            guards: vec![],
+            torches: vec![],
+            ambient: vec![],
+            end_zone: (2.0, 2.0),
+        }
+    }
+
+    #[test]
+    fn test_add_and_remove_tiles() {
+        let mut level = square_level();
+
+        assert!(!add_tile(&mut level, GridCoord::new(1, 1)));
+        assert!(add_tile(&mut level, GridCoord::new(3, 1)));
+        assert_eq!(10, level.floor_tiles().len());
+
+        // removing the center splits the room
+        assert!(remove_tile(&mut level, GridCoord::new(1, 1)));
+        assert!(!remove_tile(&mut level, GridCoord::new(1, 1)));
+        assert!(remove_tile(&mut level, GridCoord::new(3, 1)));
+
+        let tiles = level.floor_tiles();
+        assert_eq!(8, tiles.len());
+        assert!(!tiles.contains(&GridCoord::new(1, 1)));
+        assert!(tiles.contains(&GridCoord::new(0, 1)));
+        assert!(tiles.contains(&GridCoord::new(2, 1)));
+    }
+
+    #[test]
+    fn test_packs() {
+        let mut level = square_level();
+
+        assert!(!put_pack(&mut level, GridCoord::new(1, 1), &[]));
+        assert!(put_pack(
+            &mut level,
+            GridCoord::new(1, 1),
+            &[TypeCreature::Bone]
+        ));
+        assert!(put_pack(
+            &mut level,
+            GridCoord::new(1, 1),
+            &[TypeCreature::Arm]
+        ));
+        assert_eq!(1, level.packs.len());
+        assert_eq!(vec![TypeCreature::Arm], level.packs[0].items);
+        // the replacing pack is a new one
+        assert_eq!(1, level.packs[0].id);
+
+        assert!(remove_pack(&mut level, GridCoord::new(1, 1)));
+        assert!(level.packs.is_empty());
+    }
+
+    #[test]
+    fn test_chain_opens_the_moved_gate() {
+        let mut level = square_level();
+
+        move_gate(&mut level, GridCoord::new(1, 2));
+        move_chain(&mut level, GridCoord::new(0, 2));
+
+        assert_eq!((1.0, 2.65), level.gates[0].position);
+        assert_eq!(level.gates[0].id, level.chains[0].gate);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::creatures::TypeCreature;
+use crate::map::grid::GridCoord;
+use crate::map::level::Level;
+use crate::map::Campaign;
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+mod edits;
+
+/// Where a level without file (a generated dungeon) is saved, in the assets folder
+static PATH_GENERATED_LEVEL: &str = "levels/generated.level.ron";
+static PATH_FONT: &str = "fonts/FiraMono-Medium.ttf";
+
+const CAMERA_PAN_SPEED: f32 = 20.0;
+
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(EditorState::Off)
+            .insert_resource(EditorSettings {
+                enabled: editor_from_args(),
+            })
+            .init_resource::<EditorBrush>()
+            .add_system(toggle_editor)
+            .add_system_set(SystemSet::on_enter(EditorState::On).with_system(spawn_editor_text))
+            .add_system_set(SystemSet::on_exit(EditorState::On).with_system(despawn_editor_text))
+            .add_system_set(
+                SystemSet::on_update(EditorState::On)
+                    .with_system(select_tool)
+                    .with_system(edit_on_click)
+                    .with_system(move_camera)
+                    .with_system(save_level)
+                    .with_system(update_editor_text),
+            );
+    }
+}
+
+/// On: the camera stops following Skelly and mouse clicks edit the level being played
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EditorState {
+    Off,
+    On,
+}
+
+/// The editor can only be opened (with F1) when the game is started with `--editor`
+struct EditorSettings {
+    enabled: bool,
+}
+
+fn editor_from_args() -> bool {
+    std::env::args().any(|arg| arg == "--editor")
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EditorTool {
+    Floor,
+    Pack,
+    Gate,
+    Chain,
+    EndZone,
+    PlayerStart,
+}
+
+/// Ressource holding the selected tool and the items of the next pack
+struct EditorBrush {
+    tool: EditorTool,
+    items: Vec<TypeCreature>,
+}
+
+impl Default for EditorBrush {
+    fn default() -> Self {
+        Self {
+            tool: EditorTool::Floor,
+            items: vec![TypeCreature::Bone],
+        }
+    }
+}
+
+#[derive(Component)]
+struct TagEditorText;
+
+fn toggle_editor(
+    settings: Res<EditorSettings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut editor_state: ResMut<State<EditorState>>,
+) {
+    if !settings.enabled || !keyboard_input.just_pressed(KeyCode::F1) {
+        return;
+    }
+
+    let next = match editor_state.current() {
+        EditorState::Off => EditorState::On,
+        EditorState::On => EditorState::Off,
+    };
+    info!("Editor: {:?}", next);
+    editor_state.set(next).unwrap();
+}
+
+/// 1 to 6 select the tool, B, C, L and R add an item to the next pack, Backspace removes one
+fn select_tool(keyboard_input: Res<Input<KeyCode>>, mut brush: ResMut<EditorBrush>) {
+    let tools = [
+        (KeyCode::Key1, EditorTool::Floor),
+        (KeyCode::Key2, EditorTool::Pack),
+        (KeyCode::Key3, EditorTool::Gate),
+        (KeyCode::Key4, EditorTool::Chain),
+        (KeyCode::Key5, EditorTool::EndZone),
+        (KeyCode::Key6, EditorTool::PlayerStart),
+    ];
+    for (key, tool) in tools {
+        if keyboard_input.just_pressed(key) {
+            brush.tool = tool;
+        }
+    }
+
+    let items = [
+        (KeyCode::B, TypeCreature::Bone),
+        (KeyCode::C, TypeCreature::Chest),
+        (KeyCode::L, TypeCreature::Leg),
+        (KeyCode::R, TypeCreature::Arm),
+    ];
+    for (key, item) in items {
+        if keyboard_input.just_pressed(key) {
+            brush.items.push(item);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        brush.items.pop();
+    }
+}
+
+/// Point of the floor (y = 0) under the mouse cursor
+fn cursor_to_ground(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec3> {
+    let cursor = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / window_size * 2.0 - Vec2::ONE;
+
+    // two points of the ray going through the cursor
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(0.0));
+
+    let direction = far - near;
+    if direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+    Some(near - direction * (near.y / direction.y))
+}
+
+/// Left click adds or moves, right click removes
+/// The level asset is edited, then spawned again by map::reload_modified_level()
+fn edit_on_click(
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    query_camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    brush: Res<EditorBrush>,
+    campaign: Res<Campaign>,
+    mut levels: ResMut<Assets<Level>>,
+) {
+    let add = mouse_input.just_pressed(MouseButton::Left);
+    if !add && !mouse_input.just_pressed(MouseButton::Right) {
+        return;
+    }
+
+    let (camera, camera_transform) = match query_camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let tile = match windows
+        .get_primary()
+        .and_then(|window| cursor_to_ground(window, camera, camera_transform))
+    {
+        Some(position) => GridCoord::from_world(position),
+        None => return,
+    };
+    // get_mut() would send AssetEvent::Modified even without any change
+    let mut level = match levels.get(campaign.current_level()) {
+        Some(level) => level.clone(),
+        None => return,
+    };
+
+    let changed = match (brush.tool, add) {
+        (EditorTool::Floor, true) => edits::add_tile(&mut level, tile),
+        (EditorTool::Floor, false) => edits::remove_tile(&mut level, tile),
+        (EditorTool::Pack, true) => edits::put_pack(&mut level, tile, &brush.items),
+        (EditorTool::Pack, false) => edits::remove_pack(&mut level, tile),
+        (EditorTool::Gate, true) => edits::move_gate(&mut level, tile),
+        (EditorTool::Chain, true) => edits::move_chain(&mut level, tile),
+        (EditorTool::EndZone, true) => {
+            level.end_zone = (tile.i as f32, tile.j as f32);
+            true
+        }
+        (EditorTool::PlayerStart, true) => {
+            level.player_start = (tile.i as f32, tile.j as f32);
+            true
+        }
+        _ => false,
+    };
+
+    if changed {
+        info!("Editor: {:?} on ({}, {})", brush.tool, tile.i, tile.j);
+        let _ = levels.set(campaign.current_level(), level);
+    }
+}
+
+/// Arrows move the camera, it does not follow Skelly while editing
+fn move_camera(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query_camera: Query<&mut Transform, With<Camera3d>>,
+) {
+    // the camera looks towards +x +z
+    let mut direction = Vec3::ZERO;
+    if keyboard_input.pressed(KeyCode::Up) {
+        direction += Vec3::new(1.0, 0.0, 1.0);
+    }
+    if keyboard_input.pressed(KeyCode::Down) {
+        direction += Vec3::new(-1.0, 0.0, -1.0);
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        direction += Vec3::new(-1.0, 0.0, 1.0);
+    }
+    if keyboard_input.pressed(KeyCode::Left) {
+        direction += Vec3::new(1.0, 0.0, -1.0);
+    }
+
+    if direction == Vec3::ZERO {
+        return;
+    }
+
+    if let Ok(mut camera_transform) = query_camera.get_single_mut() {
+        camera_transform.translation +=
+            direction.normalize() * CAMERA_PAN_SPEED * time.delta_seconds();
+    }
+}
+
+/// F5 writes the level being played to its file
+fn save_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    campaign: Res<Campaign>,
+    levels: Res<Assets<Level>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let handle = campaign.current_level();
+    let level = match levels.get(handle) {
+        Some(level) => level,
+        None => return,
+    };
+
+    let path = asset_server
+        .get_handle_path(handle)
+        .map_or(PathBuf::from(PATH_GENERATED_LEVEL), |asset_path| {
+            asset_path.path().to_path_buf()
+        });
+    let path = PathBuf::from("assets").join(path);
+
+    let result = ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::new())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| std::fs::write(&path, text).map_err(anyhow::Error::from));
+
+    match result {
+        Ok(()) => info!("Level saved to {}", path.display()),
+        Err(error) => error!("Could not save the level to {}: {}", path.display(), error),
+    }
+}
+
+fn editor_text(brush: &EditorBrush) -> String {
+    let items: Vec<String> = brush
+        .items
+        .iter()
+        .map(|item| format!("{:?}", item))
+        .collect();
+
+    format!(
+        "EDITOR  F1: quit  F5: save  arrows: camera\n\
+         Tool [1-6]: {:?}\n\
+         Pack [B C L R, Backspace]: {}\n\
+         Left click: add / move, right click: remove",
+        brush.tool,
+        items.join(", ")
+    )
+}
+
+fn spawn_editor_text(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    brush: Res<EditorBrush>,
+) {
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                editor_text(&brush),
+                TextStyle {
+                    font: asset_server.load(PATH_FONT),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(TagEditorText)
+        .insert(Name::new("Editor text"));
+}
+
+fn despawn_editor_text(mut commands: Commands, query_text: Query<Entity, With<TagEditorText>>) {
+    for entity in query_text.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn update_editor_text(
+    brush: Res<EditorBrush>,
+    mut query_text: Query<&mut Text, With<TagEditorText>>,
+) {
+    if !brush.is_changed() {
+        return;
+    }
+
+    if let Ok(mut text) = query_text.get_single_mut() {
+        text.sections[0].value = editor_text(&brush);
+    }
+}
@@ -0,0 +1,531 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Keys of the player, in the assets folder, written again after a rebinding
+static PATH_BINDINGS: &str = "config/controls.bindings.ron";
+static PATH_FONT: &str = "fonts/FiraMono-Medium.ttf";
+
+/// Starts rebinding every key of every action, one after the other
+const KEY_REBIND: KeyCode = KeyCode::F2;
+/// Keeps the key being rebound
+const KEY_SKIP: KeyCode = KeyCode::Escape;
+
+/// Below this tilt, the left stick does not move Skelly
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+pub struct InputPlugin;
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Bindings>()
+            .init_asset_loader::<BindingsLoader>()
+            .init_resource::<InputActions>()
+            .init_resource::<Rebinding>()
+            .add_startup_system(load_bindings)
+            .add_system(apply_loaded_bindings)
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.after(InputSystem))
+            .add_system(rebind_keys);
+    }
+}
+
+/// What the player wants to do, whatever the keys bound to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Run,
+    Interact,
+    Attack,
+}
+
+impl Action {
+    /// Every action, in the order they are rebound
+    pub const ALL: [Action; 7] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Run,
+        Action::Interact,
+        Action::Attack,
+    ];
+}
+
+/// Keys bound to each action, both AZERTY and QWERTY by default,
+/// and gamepad buttons, the left stick moves Skelly as well
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, TypeUuid)]
+#[uuid = "9c3884ac-81f9-4575-a77b-85308bcbc6d7"]
+pub struct Bindings {
+    keys: BTreeMap<Action, Vec<KeyCode>>,
+    #[serde(default = "default_buttons")]
+    buttons: BTreeMap<Action, Vec<GamepadButtonType>>,
+}
+
+fn default_buttons() -> BTreeMap<Action, Vec<GamepadButtonType>> {
+    BTreeMap::from([
+        (Action::MoveUp, vec![GamepadButtonType::DPadUp]),
+        (Action::MoveDown, vec![GamepadButtonType::DPadDown]),
+        (Action::MoveLeft, vec![GamepadButtonType::DPadLeft]),
+        (Action::MoveRight, vec![GamepadButtonType::DPadRight]),
+        (
+            Action::Run,
+            vec![GamepadButtonType::South, GamepadButtonType::RightTrigger2],
+        ),
+        (Action::Interact, vec![GamepadButtonType::East]),
+        (Action::Attack, vec![GamepadButtonType::West]),
+    ])
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: BTreeMap::from([
+                (Action::MoveUp, vec![KeyCode::Z, KeyCode::W]),
+                (Action::MoveDown, vec![KeyCode::S]),
+                (Action::MoveLeft, vec![KeyCode::Q, KeyCode::A]),
+                (Action::MoveRight, vec![KeyCode::D]),
+                (Action::Run, vec![KeyCode::LShift]),
+                (Action::Interact, vec![KeyCode::E]),
+                (Action::Attack, vec![KeyCode::Space]),
+            ]),
+            buttons: default_buttons(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn buttons(&self, action: Action) -> &[GamepadButtonType] {
+        self.buttons
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Replaces the key in `slot`, or adds it after the bound keys,
+    /// the other keys of `action` are kept and other actions lose it
+    pub fn rebind(&mut self, action: Action, slot: usize, key: KeyCode) {
+        for (bound_action, keys) in self.keys.iter_mut() {
+            if *bound_action != action {
+                keys.retain(|bound| *bound != key);
+            }
+        }
+
+        let keys = self.keys.entry(action).or_default();
+        let slot = match keys.get_mut(slot) {
+            Some(bound) => {
+                *bound = key;
+                slot
+            }
+            None => {
+                keys.push(key);
+                keys.len() - 1
+            }
+        };
+        let mut index = 0;
+        keys.retain(|bound| {
+            let keep = *bound != key || index == slot;
+            index += 1;
+            keep
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct BindingsLoader;
+
+impl AssetLoader for BindingsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let bindings = ron::de::from_bytes::<Bindings>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(bindings));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bindings.ron"]
+    }
+}
+
+/// Ressource read by the gameplay systems instead of the raw keys
+/// Updated at the start of each frame from the keyboard and the first gamepad
+#[derive(Default)]
+pub struct InputActions {
+    bindings: Bindings,
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// left stick, x goes right and y goes up, zero inside the dead zone
+    movement: Vec2,
+}
+
+impl InputActions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// Analog movement of the left stick, its length goes from 0 to 1
+    pub fn movement(&self) -> Vec2 {
+        self.movement
+    }
+
+    fn update(
+        &mut self,
+        keyboard_input: &Input<KeyCode>,
+        gamepad: Option<Gamepad>,
+        gamepad_buttons: &Input<GamepadButton>,
+        gamepad_axes: &Axis<GamepadAxis>,
+    ) {
+        self.clear();
+
+        for action in Action::ALL {
+            let keys = self.bindings.keys(action);
+            let buttons: Vec<GamepadButton> = gamepad
+                .map(|gamepad| {
+                    self.bindings
+                        .buttons(action)
+                        .iter()
+                        .map(|button_type| GamepadButton(gamepad, *button_type))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if keys.iter().any(|key| keyboard_input.pressed(*key))
+                || buttons
+                    .iter()
+                    .any(|button| gamepad_buttons.pressed(*button))
+            {
+                self.pressed.insert(action);
+            }
+            if keys.iter().any(|key| keyboard_input.just_pressed(*key))
+                || buttons
+                    .iter()
+                    .any(|button| gamepad_buttons.just_pressed(*button))
+            {
+                self.just_pressed.insert(action);
+            }
+        }
+
+        if let Some(gamepad) = gamepad {
+            let axis = |axis_type| {
+                gamepad_axes
+                    .get(GamepadAxis(gamepad, axis_type))
+                    .unwrap_or(0.0)
+            };
+            self.movement = apply_dead_zone(Vec2::new(
+                axis(GamepadAxisType::LeftStickX),
+                axis(GamepadAxisType::LeftStickY),
+            ));
+        }
+    }
+
+    /// No action is active until the next update, used while rebinding
+    fn clear(&mut self) {
+        self.pressed.clear();
+        self.just_pressed.clear();
+        self.movement = Vec2::ZERO;
+    }
+}
+
+/// Zero inside the dead zone, then the length grows from 0 to 1 at full tilt
+fn apply_dead_zone(stick: Vec2) -> Vec2 {
+    let length = stick.length();
+    if length < STICK_DEAD_ZONE {
+        return Vec2::ZERO;
+    }
+
+    let scaled = ((length - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE)).min(1.0);
+    stick / length * scaled
+}
+
+/// Ressource holding the handle of the bindings file and the key being rebound, if any:
+/// the index of its action in Action::ALL and its slot
+#[derive(Default)]
+struct Rebinding {
+    handle: Handle<Bindings>,
+    current: Option<(usize, usize)>,
+}
+
+#[derive(Component)]
+struct TagRebindText;
+
+fn load_bindings(asset_server: Res<AssetServer>, mut rebinding: ResMut<Rebinding>) {
+    rebinding.handle = asset_server.load(PATH_BINDINGS);
+}
+
+/// The default bindings are kept until the file is loaded, and when it is missing
+fn apply_loaded_bindings(
+    mut asset_events: EventReader<AssetEvent<Bindings>>,
+    bindings: Res<Assets<Bindings>>,
+    rebinding: Res<Rebinding>,
+    mut input_actions: ResMut<InputActions>,
+) {
+    for event in asset_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+                if *handle == rebinding.handle =>
+            {
+                if let Some(loaded) = bindings.get(handle) {
+                    info!("Bindings loaded: {:?}", loaded);
+                    input_actions.bindings = loaded.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The gamepad with the lowest id is the one playing
+fn update_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    rebinding: Res<Rebinding>,
+    mut input_actions: ResMut<InputActions>,
+) {
+    if rebinding.current.is_some() {
+        input_actions.clear();
+    } else {
+        let gamepad = gamepads.iter().min_by_key(|gamepad| gamepad.0).copied();
+        input_actions.update(&keyboard_input, gamepad, &gamepad_buttons, &gamepad_axes);
+    }
+}
+
+/// F2 asks for a new key for each key of each action, Escape keeps the current one
+/// The bindings are saved once the last action is bound
+fn rebind_keys(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut input_actions: ResMut<InputActions>,
+    mut query_text: Query<(Entity, &mut Text), With<TagRebindText>>,
+) {
+    let (index, slot) = match rebinding.current {
+        Some(current) => current,
+        None => {
+            if keyboard_input.just_pressed(KEY_REBIND) {
+                rebinding.current = Some((0, 0));
+                spawn_rebind_text(
+                    &mut commands,
+                    &asset_server,
+                    rebind_text(Action::ALL[0], 0, &input_actions.bindings),
+                );
+            }
+            return;
+        }
+    };
+
+    let action = Action::ALL[index];
+    if !keyboard_input.just_pressed(KEY_SKIP) {
+        match keyboard_input
+            .get_just_pressed()
+            .find(|key| **key != KEY_REBIND)
+        {
+            Some(key) => {
+                info!("{:?} bound to {:?}", action, key);
+                input_actions.bindings.rebind(action, slot, *key);
+            }
+            None => return,
+        }
+    }
+
+    // an action without keys is asked for one
+    let next = if slot + 1 < input_actions.bindings.keys(action).len() {
+        Some((index, slot + 1))
+    } else if index + 1 < Action::ALL.len() {
+        Some((index + 1, 0))
+    } else {
+        None
+    };
+    if let Some((next_index, next_slot)) = next {
+        rebinding.current = next;
+        for (_, mut text) in query_text.iter_mut() {
+            text.sections[0].value =
+                rebind_text(Action::ALL[next_index], next_slot, &input_actions.bindings);
+        }
+        return;
+    }
+
+    rebinding.current = None;
+    for (entity, _) in query_text.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    save_bindings(&input_actions.bindings);
+}
+
+fn rebind_text(action: Action, slot: usize, bindings: &Bindings) -> String {
+    match bindings.keys(action).get(slot) {
+        Some(key) => format!(
+            "Press a key for {:?} ({}/{})\nEscape: keep {:?}",
+            action,
+            slot + 1,
+            bindings.keys(action).len(),
+            key
+        ),
+        None => format!("Press a key for {:?}\nEscape: no key", action),
+    }
+}
+
+fn spawn_rebind_text(commands: &mut Commands, asset_server: &Res<AssetServer>, text: String) {
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load(PATH_FONT),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(TagRebindText)
+        .insert(Name::new("Rebind text"));
+}
+
+fn save_bindings(bindings: &Bindings) {
+    let path = PathBuf::from("assets").join(PATH_BINDINGS);
+
+    let result = ron::ser::to_string_pretty(bindings, ron::ser::PrettyConfig::new())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| std::fs::write(&path, text).map_err(anyhow::Error::from));
+
+    match result {
+        Ok(()) => info!("Bindings saved to {}", path.display()),
+        Err(error) => error!(
+            "Could not save the bindings to {}: {}",
+            path.display(),
+            error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controls_file_parses() {
+        let bindings: Bindings =
+            ron::de::from_str(include_str!("../../assets/config/controls.bindings.ron")).unwrap();
+
+        assert_eq!(Bindings::default(), bindings);
+    }
+
+    #[test]
+    fn test_actions_follow_rebinding() {
+        let mut keyboard_input = Input::<KeyCode>::default();
+        let mut input_actions = InputActions::default();
+
+        let gamepad_buttons = Input::<GamepadButton>::default();
+        let gamepad_axes = Axis::<GamepadAxis>::default();
+
+        keyboard_input.press(KeyCode::W);
+        input_actions.update(&keyboard_input, None, &gamepad_buttons, &gamepad_axes);
+        assert!(input_actions.pressed(Action::MoveUp));
+        assert!(input_actions.just_pressed(Action::MoveUp));
+
+        // W moves down now, and is no longer bound to MoveUp
+        input_actions
+            .bindings
+            .rebind(Action::MoveDown, 0, KeyCode::W);
+        keyboard_input.clear();
+        input_actions.update(&keyboard_input, None, &gamepad_buttons, &gamepad_axes);
+        assert!(!input_actions.pressed(Action::MoveUp));
+        assert!(input_actions.pressed(Action::MoveDown));
+        assert!(!input_actions.just_pressed(Action::MoveDown));
+        assert_eq!(&[KeyCode::Z], input_actions.bindings.keys(Action::MoveUp));
+    }
+
+    #[test]
+    fn test_rebind_one_of_two_keys() {
+        let mut bindings = Bindings::default();
+
+        // the QWERTY key is kept
+        bindings.rebind(Action::MoveUp, 0, KeyCode::Up);
+        assert_eq!(&[KeyCode::Up, KeyCode::W], bindings.keys(Action::MoveUp));
+
+        // already in the first slot: it is only kept in the second one
+        bindings.rebind(Action::MoveUp, 1, KeyCode::Up);
+        assert_eq!(&[KeyCode::Up], bindings.keys(Action::MoveUp));
+
+        // a slot after the bound keys adds a key
+        bindings.rebind(Action::MoveUp, 1, KeyCode::Z);
+        assert_eq!(&[KeyCode::Up, KeyCode::Z], bindings.keys(Action::MoveUp));
+    }
+
+    #[test]
+    fn test_gamepad_stick_and_run_button() {
+        let mut app = App::new();
+        app.add_plugin(bevy::input::InputPlugin)
+            .init_resource::<InputActions>()
+            .init_resource::<Rebinding>()
+            .add_system(update_actions);
+
+        let gamepad = Gamepad(0);
+        app.world
+            .resource_mut::<Events<GamepadEvent>>()
+            .send(GamepadEvent(gamepad, GamepadEventType::Connected));
+
+        // inside the dead zone
+        app.world
+            .resource_mut::<Axis<GamepadAxis>>()
+            .set(GamepadAxis(gamepad, GamepadAxisType::LeftStickY), 0.1);
+        app.update();
+        assert_eq!(Vec2::ZERO, app.world.resource::<InputActions>().movement());
+
+        app.world
+            .resource_mut::<Axis<GamepadAxis>>()
+            .set(GamepadAxis(gamepad, GamepadAxisType::LeftStickY), 1.0);
+        app.world
+            .resource_mut::<Input<GamepadButton>>()
+            .press(GamepadButton(gamepad, GamepadButtonType::South));
+        app.update();
+
+        let input_actions = app.world.resource::<InputActions>();
+        assert_eq!(Vec2::Y, input_actions.movement());
+        assert!(input_actions.pressed(Action::Run));
+        assert!(!input_actions.pressed(Action::MoveUp));
+    }
+
+    #[test]
+    fn test_dead_zone() {
+        assert_eq!(Vec2::ZERO, apply_dead_zone(Vec2::new(0.1, -0.1)));
+
+        let half = apply_dead_zone(Vec2::X * (1.0 + STICK_DEAD_ZONE) / 2.0);
+        assert!((half.x - 0.5).abs() < 1e-5);
+        // never faster than at full tilt
+        assert!((apply_dead_zone(Vec2::new(1.0, 1.0)).length() - 1.0).abs() < 1e-5);
+    }
+}
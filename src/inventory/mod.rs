@@ -1,14 +1,17 @@
 mod ui;
 
 use crate::creatures::{
-    BoneTag, Creature, SceneModelState, ToDespawn, TypeCreature, ARMS_NEEDED_FULL_BODY,
+    BoneTag, Creature, Player, SceneModelState, ToDespawn, TypeCreature, ARMS_NEEDED_FULL_BODY,
     BONES_NEEDED_FULL_BODY, BONES_NEEDED_HALF_BODY, CHEST_NEEDED_FULL_BODY, CHEST_NEEDED_HALF_BODY,
     LEGS_NEEDED_FULL_BODY, LEGS_NEEDED_HALF_BODY,
 };
 use crate::inventory::ui::InventoryTextTag;
+use crate::trigger_zone::{TriggerAction, TriggerEvent};
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_inspector_egui::Inspectable;
 use bevy_rapier3d::prelude::CollisionEvent;
+use serde::{Deserialize, Serialize};
 
 pub const STARTING_NB_BONES: usize = 0;
 pub const STARTING_NB_CHEST: usize = 0;
@@ -28,14 +31,15 @@ impl Plugin for InventoryPlugin {
             .add_startup_system(ui::setup_ui)
             .add_system(update_inventory_text)
             .add_system_to_stage(CoreStage::PostUpdate, update_inventory_on_pickup)
-            .add_system(update_game_state_on_inventory);
+            .add_system(update_game_state_on_inventory)
+            .add_system(add_key_on_trigger);
     }
 }
 
 #[derive(Component)]
 pub struct Pickupable;
 
-#[derive(Component, Eq, PartialEq, Inspectable)]
+#[derive(Component, Debug, Clone, Copy, Eq, PartialEq, Inspectable, Deserialize, Serialize)]
 pub enum ItemType {
     None,
     Chest,
@@ -60,6 +64,34 @@ impl ItemType {
             ItemType::Leg => String::from(TEXT_INV_LEG),
         }
     }
+
+    /// Part picked up to get this item
+    pub(crate) fn part(&self) -> Option<TypeCreature> {
+        match self {
+            ItemType::None => None,
+            ItemType::Bone => Some(TypeCreature::Bone),
+            ItemType::Chest => Some(TypeCreature::Chest),
+            ItemType::Arm => Some(TypeCreature::Arm),
+            ItemType::Leg => Some(TypeCreature::Leg),
+        }
+    }
+
+    /// Name used in sentences, "2 legs"
+    pub(crate) fn get_name(&self, count: usize) -> String {
+        let name = match self {
+            ItemType::None => "nothing",
+            ItemType::Bone => "bone",
+            ItemType::Chest => "chest",
+            ItemType::Arm => "arm",
+            ItemType::Leg => "leg",
+        };
+
+        if count > 1 {
+            format!("{}s", name)
+        } else {
+            String::from(name)
+        }
+    }
 }
 
 #[derive(Component, Inspectable, Default)]
@@ -71,6 +103,9 @@ struct InventoryEntry {
 #[derive(Component, Inspectable)]
 pub struct Inventory {
     items: [InventoryEntry; 4],
+    /// keys opening gates, found in bone packs
+    #[inspectable(ignore)]
+    keys: HashSet<String>,
 }
 
 impl Inventory {
@@ -90,6 +125,33 @@ impl Inventory {
         self.items[3].count += count;
     }
 
+    pub(crate) fn add_key(&mut self, key: &str) {
+        self.keys.insert(String::from(key));
+    }
+
+    pub(crate) fn has_key(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub(crate) fn count(&self, item: ItemType) -> usize {
+        self.items
+            .iter()
+            .filter(|entry| entry.item == item)
+            .map(|entry| entry.count)
+            .sum()
+    }
+
+    /// Removes up to `count` items, returns how many were actually removed
+    pub(crate) fn remove(&mut self, item: ItemType, count: usize) -> usize {
+        let mut removed = 0;
+        for entry in self.items.iter_mut().filter(|entry| entry.item == item) {
+            let taken = entry.count.min(count - removed);
+            entry.count -= taken;
+            removed += taken;
+        }
+        removed
+    }
+
     fn number_of_bones(&self) -> usize {
         self.items[0].count
     }
@@ -165,6 +227,7 @@ impl Default for Inventory {
                     count: STARTING_NB_CHEST,
                 },
             ],
+            keys: HashSet::default(),
         }
     }
 }
@@ -220,6 +283,21 @@ fn update_inventory_on_pickup(
     }
 }
 
+/// Handles the GiveKey action of the bone packs
+fn add_key_on_trigger(
+    mut trigger_events: EventReader<TriggerEvent>,
+    mut query_inventory: Query<&mut Inventory, With<Player>>,
+) {
+    for TriggerEvent(action) in trigger_events.iter() {
+        if let TriggerAction::GiveKey(key) = action {
+            if let Ok(mut inventory) = query_inventory.get_single_mut() {
+                info!("Found the {}", key);
+                inventory.add_key(key);
+            }
+        }
+    }
+}
+
 fn update_game_state_on_inventory(
     query_inventory: Query<&Inventory>,
     mut app_state: ResMut<State<SceneModelState>>,
@@ -240,3 +318,22 @@ fn update_game_state_on_inventory(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove() {
+        let mut inventory = Inventory::default();
+        inventory.add_bone(3);
+        inventory.add_legs(1);
+
+        assert_eq!(2, inventory.remove(ItemType::Bone, 2));
+        assert_eq!(1, inventory.count(ItemType::Bone));
+
+        // never goes below 0
+        assert_eq!(1, inventory.remove(ItemType::Leg, 5));
+        assert_eq!(0, inventory.count(ItemType::Leg));
+    }
+}
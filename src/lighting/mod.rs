@@ -0,0 +1,228 @@
+use crate::creatures::Player;
+use crate::map::grid::{grid_to_world, GridCoord, TileMap};
+use crate::map::level::{Level, LevelAmbient, LevelTorch};
+use crate::map::{Campaign, LevelReady, TagLevelEntity};
+use bevy::prelude::*;
+use std::borrow::BorrowMut;
+
+/// Half size of the box around the camera target where the sun casts shadows
+const SHADOW_HALF_SIZE: f32 = 10.0;
+
+/// Ambient brightness outside of the areas listed by the level
+const DEFAULT_AMBIENT_BRIGHTNESS: f32 = 0.05;
+/// How fast the ambient light reaches the brightness of a new room, per second
+const AMBIENT_FADE_SPEED: f32 = 2.0;
+
+const TORCH_HEIGHT: f32 = 2.5;
+const TORCH_RANGE: f32 = 8.0;
+/// The intensity of a torch varies by up to 20%
+const TORCH_FLICKER: f32 = 0.2;
+
+pub struct LightingPlugin;
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AmbientLight {
+            brightness: DEFAULT_AMBIENT_BRIGHTNESS,
+            ..default()
+        })
+        .add_startup_system(setup_sun)
+        .add_system(move_shadows_with_camera)
+        .add_system(setup_torches)
+        .add_system(flicker_torches)
+        .add_system(update_room_ambient);
+    }
+}
+
+#[derive(Component)]
+struct TagSun;
+
+/// Point light of a torch, flickering around `intensity`
+#[derive(Component)]
+struct Torch {
+    intensity: f32,
+    /// so that torches do not flicker together
+    phase: f32,
+}
+
+fn setup_sun(mut commands: Commands) {
+    commands
+        .spawn_bundle(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadow_projection: OrthographicProjection {
+                    left: -SHADOW_HALF_SIZE,
+                    right: SHADOW_HALF_SIZE,
+                    bottom: -SHADOW_HALF_SIZE,
+                    top: SHADOW_HALF_SIZE,
+                    near: -10.0 * SHADOW_HALF_SIZE,
+                    far: 10.0 * SHADOW_HALF_SIZE,
+                    ..default()
+                },
+                shadows_enabled: true,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 2.0, 0.0),
+                rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_4),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(TagSun)
+        .insert(Name::new("SunLight"));
+}
+
+/// The shadow box is centered on the sun, it is moved to the point of the floor seen by the camera
+fn move_shadows_with_camera(
+    query_camera: Query<&Transform, (With<Camera3d>, Changed<Transform>)>,
+    mut query_sun: Query<&mut Transform, (With<TagSun>, Without<Camera3d>)>,
+) {
+    if let (Ok(camera_transform), Ok(mut sun_transform)) =
+        (query_camera.get_single(), query_sun.get_single_mut())
+    {
+        let forward = camera_transform.forward();
+        if forward.y.abs() < f32::EPSILON {
+            return;
+        }
+        let target =
+            camera_transform.translation - forward * (camera_transform.translation.y / forward.y);
+        sun_transform.translation = target;
+    }
+}
+
+fn setup_torches(
+    mut commands: Commands,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+) {
+    for event in level_events.iter() {
+        if let Some(level) = levels.get(&event.0) {
+            for torch in &level.torches {
+                spawn_torch(commands.borrow_mut(), torch);
+            }
+        }
+    }
+}
+
+fn spawn_torch(commands: &mut Commands, torch: &LevelTorch) {
+    let position = grid_to_world(torch.position.0, torch.position.1);
+    let (r, g, b) = torch.color;
+
+    commands
+        .spawn_bundle(PointLightBundle {
+            point_light: PointLight {
+                color: Color::rgb(r, g, b),
+                intensity: torch.intensity,
+                range: TORCH_RANGE,
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, TORCH_HEIGHT, position.z),
+            ..default()
+        })
+        .insert(Torch {
+            intensity: torch.intensity,
+            phase: position.x * 1.7 + position.z * 3.1,
+        })
+        .insert(TagLevelEntity)
+        .insert(Name::new(format!(
+            "Torch ({}, {})",
+            torch.position.0, torch.position.1
+        )));
+}
+
+/// Intensity factor of a torch, between 1 - TORCH_FLICKER and 1 + TORCH_FLICKER
+fn flicker(seconds: f32, phase: f32) -> f32 {
+    let noise = 0.5 * (seconds * 7.3 + phase).sin()
+        + 0.3 * (seconds * 13.1 + 2.0 * phase).sin()
+        + 0.2 * (seconds * 23.7 + 3.0 * phase).sin();
+    1.0 + TORCH_FLICKER * noise
+}
+
+fn flicker_torches(time: Res<Time>, mut query_torches: Query<(&Torch, &mut PointLight)>) {
+    let seconds = time.seconds_since_startup() as f32;
+    for (torch, mut light) in query_torches.iter_mut() {
+        light.intensity = torch.intensity * flicker(seconds, torch.phase);
+    }
+}
+
+/// Brightness of the first area containing the tile
+fn ambient_at(ambient: &[LevelAmbient], tile: GridCoord) -> f32 {
+    ambient
+        .iter()
+        .find(|ambient| {
+            (ambient.area.min.i..=ambient.area.max.i).contains(&tile.i)
+                && (ambient.area.min.j..=ambient.area.max.j).contains(&tile.j)
+        })
+        .map_or(DEFAULT_AMBIENT_BRIGHTNESS, |ambient| ambient.brightness)
+}
+
+/// Fades the ambient light to the brightness of the room under Skelly
+fn update_room_ambient(
+    time: Res<Time>,
+    campaign: Res<Campaign>,
+    levels: Res<Assets<Level>>,
+    tile_map: Res<TileMap>,
+    query_player: Query<&Transform, With<Player>>,
+    mut ambient_light: ResMut<AmbientLight>,
+) {
+    let level = match levels.get(campaign.current_level()) {
+        Some(level) => level,
+        None => return,
+    };
+    let tile = match query_player
+        .get_single()
+        .ok()
+        .and_then(|player_transform| tile_map.tile_at(player_transform.translation))
+    {
+        Some(tile) => tile,
+        None => return,
+    };
+
+    let target = ambient_at(&level.ambient, tile);
+    let difference = target - ambient_light.brightness;
+    // only borrowing mutably when needed, the brightness is extracted when changed
+    if difference.abs() > f32::EPSILON {
+        ambient_light.brightness +=
+            difference * (AMBIENT_FADE_SPEED * time.delta_seconds()).min(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::level::FloorRect;
+
+    #[test]
+    fn test_ambient_of_first_area() {
+        let ambient = vec![
+            LevelAmbient {
+                area: FloorRect {
+                    min: GridCoord::new(0, 0),
+                    max: GridCoord::new(2, 2),
+                },
+                brightness: 0.01,
+            },
+            LevelAmbient {
+                area: FloorRect {
+                    min: GridCoord::new(2, 0),
+                    max: GridCoord::new(4, 0),
+                },
+                brightness: 0.5,
+            },
+        ];
+
+        assert_eq!(0.01, ambient_at(&ambient, GridCoord::new(2, 0)));
+        assert_eq!(0.5, ambient_at(&ambient, GridCoord::new(4, 0)));
+        assert_eq!(
+            DEFAULT_AMBIENT_BRIGHTNESS,
+            ambient_at(&ambient, GridCoord::new(5, 5))
+        );
+    }
+
+    #[test]
+    fn test_flicker_stays_around_intensity() {
+        for step in 0..1000 {
+            let factor = flicker(step as f32 * 0.01, 1.3);
+            assert!((1.0 - TORCH_FLICKER..=1.0 + TORCH_FLICKER).contains(&factor));
+        }
+    }
+}
@@ -1,17 +1,23 @@
 mod animations_handler;
 mod camera;
+mod combat;
 mod creatures;
 mod directions;
+mod editor;
+mod input;
 mod inventory;
+mod lighting;
 mod map;
+mod minimap;
+mod trigger_zone;
 mod ui_text;
 
+use bevy::asset::AssetServerSettings;
 use bevy::log::LogSettings;
 use bevy::prelude::*;
 use bevy::window::PresentMode;
 
 use crate::animations_handler::{AddAnimation, HashMapAnimationClip, SceneHandle};
-use crate::creatures::skelly::SkellyAnimationId;
 use bevy_rapier3d::prelude::*;
 
 mod settings {
@@ -25,34 +31,6 @@ mod settings {
     pub const WINDOW_MODE: WindowMode = WindowMode::Windowed;
 }
 
-fn setup_light(mut commands: Commands) {
-    // light
-    const HALF_SIZE: f32 = 10.0;
-    commands
-        .spawn_bundle(DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                shadow_projection: OrthographicProjection {
-                    left: -HALF_SIZE,
-                    right: HALF_SIZE,
-                    bottom: -HALF_SIZE,
-                    top: HALF_SIZE,
-                    near: -10.0 * HALF_SIZE,
-                    far: 10.0 * HALF_SIZE,
-                    ..default()
-                },
-                shadows_enabled: true,
-                ..default()
-            },
-            transform: Transform {
-                translation: Vec3::new(0.0, 2.0, 0.0),
-                rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_4),
-                ..default()
-            },
-            ..default()
-        })
-        .insert(Name::new("SunLight"));
-}
-
 fn main() {
     App::new()
 
@@ -78,6 +56,13 @@ fn main() {
             level: bevy::log::Level::DEBUG,
         })
 
+        /* Assets */
+        // level files are spawned again when they change on disk
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(debug_assertions),
+            ..default()
+        })
+
         /* DefaultPlugins */
         .add_plugins(DefaultPlugins)
 
@@ -89,13 +74,18 @@ fn main() {
         // .add_plugin(RapierDebugRenderPlugin::default())
 
         /* My stuff */
+        .add_plugin(input::InputPlugin)
         .add_plugin(map::MapPlugin)
         .add_plugin(camera::CameraPlugin)
         .add_plugin(animations_handler::AnimationHandler)
         .add_plugin(creatures::CreaturePlugin)
         .add_plugin(inventory::InventoryPlugin)
         .add_plugin(ui_text::UiTextPlugin)
-        .add_startup_system(setup_light)
+        .add_plugin(trigger_zone::TriggerZonePlugin)
+        .add_plugin(minimap::MinimapPlugin)
+        .add_plugin(editor::EditorPlugin)
+        .add_plugin(lighting::LightingPlugin)
+        .add_plugin(combat::CombatPlugin)
 
         .run();
 }
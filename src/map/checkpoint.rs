@@ -0,0 +1,68 @@
+use crate::map::grid::grid_to_world;
+use crate::map::level::{Level, LevelCheckpoint};
+use crate::map::{LevelReady, TagLevelEntity};
+use crate::trigger_zone::{TriggerAction, TriggerFilter, TriggerZone};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::borrow::BorrowMut;
+
+/// Half size of the sensor, a bit smaller than a tile
+const CHECKPOINT_HALF_SIZE: f32 = 2.0;
+
+pub struct CheckpointPlugin;
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(setup_checkpoints);
+    }
+}
+
+#[derive(Component)]
+pub struct TagCheckpoint;
+
+fn setup_checkpoints(
+    mut commands: Commands,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+) {
+    for event in level_events.iter() {
+        if let Some(level) = levels.get(&event.0) {
+            for checkpoint in &level.checkpoints {
+                spawn_checkpoint(commands.borrow_mut(), checkpoint);
+            }
+        }
+    }
+}
+
+/// Invisible sensor recording its position as Skelly's respawn point each time he walks on it
+fn spawn_checkpoint(commands: &mut Commands, checkpoint: &LevelCheckpoint) {
+    let position = grid_to_world(checkpoint.position.0, checkpoint.position.1);
+
+    commands
+        .spawn_bundle(PbrBundle {
+            transform: Transform {
+                translation: Vec3::new(position.x, 0.5, position.z),
+                rotation: Quat::from_rotation_y(0.8),
+                scale: Vec3::ONE,
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(RigidBody::Fixed)
+        .insert(Collider::cuboid(
+            CHECKPOINT_HALF_SIZE,
+            0.1,
+            CHECKPOINT_HALF_SIZE,
+        ))
+        .insert(Sensor)
+        .insert(
+            TriggerZone::new(vec![TriggerAction::SetCheckpoint(position)])
+                .repeatable()
+                .with_filter(TriggerFilter::Player),
+        )
+        .insert(TagCheckpoint)
+        .insert(TagLevelEntity)
+        .insert(Name::new(format!(
+            "Checkpoint ({}, {})",
+            checkpoint.position.0, checkpoint.position.1
+        )));
+}
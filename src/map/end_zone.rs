@@ -1,5 +1,7 @@
-use crate::map::{I_SHIFT, J_SHIFT};
-use crate::ui_text::display_text;
+use crate::map::grid::grid_to_world;
+use crate::map::level::Level;
+use crate::map::{Campaign, LevelReady, LevelState, TagLevelEntity};
+use crate::trigger_zone::{TriggerAction, TriggerEvent, TriggerFilter, TriggerZone};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::borrow::BorrowMut;
@@ -9,17 +11,37 @@ static TEXT_WINNING: &str = "Congratulation, you've finished the game!\n Thanks
 pub struct EndZonePlugin;
 impl Plugin for EndZonePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_end_zone)
-            .add_system(entering_zone);
+        app.add_system(setup_end_zone)
+            .add_system(finish_level_on_trigger);
     }
 }
 
-#[derive(Component)]
-struct TagEndingZone;
+fn setup_end_zone(
+    mut commands: Commands,
+    levels: Res<Assets<Level>>,
+    campaign: Res<Campaign>,
+    mut level_events: EventReader<LevelReady>,
+) {
+    for event in level_events.iter() {
+        if let Some(level) = levels.get(&event.0) {
+            spawn_end_zone(commands.borrow_mut(), level, campaign.has_next_level());
+        }
+    }
+}
 
-fn setup_end_zone(mut commands: Commands) {
+/// The end zone of the last level only displays the winning text
+fn spawn_end_zone(commands: &mut Commands, level: &Level, has_next_level: bool) {
     let size = 12.0;
-    let position = 7.0 * I_SHIFT + 15.0 * J_SHIFT;
+    let position = grid_to_world(level.end_zone.0, level.end_zone.1);
+
+    let action = if has_next_level {
+        TriggerAction::FinishLevel
+    } else {
+        TriggerAction::ShowText {
+            text: String::from(TEXT_WINNING),
+            color: Color::YELLOW_GREEN,
+        }
+    };
 
     commands
         .spawn_bundle(PbrBundle {
@@ -34,32 +56,26 @@ fn setup_end_zone(mut commands: Commands) {
         .insert(RigidBody::Fixed)
         .insert(Collider::cuboid(size / 2.0, 0.1, size / 2.0))
         .insert(Sensor)
-        .insert(TagEndingZone)
+        .insert(
+            TriggerZone::new(vec![action])
+                .with_filter(TriggerFilter::Player)
+                .with_key("end zone"),
+        )
+        .insert(TagLevelEntity)
         .insert(Name::new("Ending Zone"));
 }
 
-fn entering_zone(
-    mut event_collision: EventReader<CollisionEvent>,
-    mut command: Commands,
-    asset_server: Res<AssetServer>,
-    query_zone: Query<&TagEndingZone>,
+/// Moves to the next level of the campaign
+fn finish_level_on_trigger(
+    mut trigger_events: EventReader<TriggerEvent>,
+    mut level_state: ResMut<State<LevelState>>,
 ) {
-    for event in event_collision.iter() {
-        match event {
-            CollisionEvent::Started(entity_a, entity_b, _) => {
-                for entity in [entity_a, entity_b] {
-                    if let Ok(_zone) = query_zone.get(*entity) {
-                        info!("Entering End Zone!");
-                        display_text(
-                            command.borrow_mut(),
-                            &asset_server,
-                            TEXT_WINNING,
-                            Color::YELLOW_GREEN,
-                        );
-                    }
-                }
+    for TriggerEvent(action) in trigger_events.iter() {
+        if let TriggerAction::FinishLevel = action {
+            info!("Entering End Zone!");
+            if *level_state.current() == LevelState::Playing {
+                level_state.set(LevelState::Finished).unwrap();
             }
-            CollisionEvent::Stopped(_, _, _) => {}
         }
     }
 }
@@ -1,40 +1,127 @@
-use crate::creatures::SceneModelState;
-use crate::map::{I_SHIFT, J_SHIFT, PATH_GLTF_CHAIN, PATH_GLTF_GATE};
-use crate::ui_text::{display_text, TagUiText, TEXT_HELP_NO_ARM};
+use crate::creatures::{Player, SceneModelState};
+use crate::inventory::{Inventory, ItemType};
+use crate::map::grid::{grid_to_world, GridCoord};
+use crate::map::level::{Level, LevelChain, LevelGate};
+use crate::map::{LevelLoad, LevelReady, TagLevelEntity, PATH_GLTF_CHAIN, PATH_GLTF_GATE};
+use crate::trigger_zone::{TriggerAction, TriggerEvent, TriggerFilter, TriggerZone};
+use crate::ui_text::{display_text, TEXT_HELP_NO_ARM, TEXT_HELP_NO_BODY};
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
 
+/// Height under which an opening gate is considered opened
+const GATE_OPENED_HEIGHT: f32 = -3.3;
+const GATE_OPENING_SPEED: f32 = 0.01;
+
 pub(crate) struct GatePlugin;
 impl Plugin for GatePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state(GateState::Closed)
-            .add_startup_system(setup_gate_chain)
-            .add_system(collision_with_chain_text)
-            .add_system(collision_with_chain_door.after(collision_with_chain_text))
-            .add_system_set(SystemSet::on_enter(GateState::Opening).with_system(tag_gate_to_remove))
+        app.init_resource::<OpenedGates>()
+            .add_system(setup_gate_chain)
+            .add_system(open_gate_on_trigger)
             .add_system(removing_gate);
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum GateState {
     Closed,
     Opening,
     Opened,
 }
 
-#[derive(Component)]
-pub struct TagChain;
+/// What Skelly needs to open a gate, declared per gate in the level file:
+/// `BodyState(FullBody)`, `Items(item: Bone, count: 20)` or `Key("crypt key")`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum GateRequirement {
+    /// Skelly's body must be at least this complete
+    BodyState(SceneModelState),
+    /// Skelly's inventory must hold at least `count` of `item`
+    Items { item: ItemType, count: usize },
+    /// Skelly must have found this key in a bone pack
+    Key(String),
+}
+
+impl GateRequirement {
+    pub fn is_met(&self, body: &SceneModelState, inventory: &Inventory) -> bool {
+        match self {
+            GateRequirement::BodyState(needed) => body.is_at_least(needed),
+            GateRequirement::Items { item, count } => inventory.count(*item) >= *count,
+            GateRequirement::Key(key) => inventory.has_key(key),
+        }
+    }
+
+    /// Text displayed when Skelly pulls the chain without meeting this requirement
+    pub fn rejection_text(&self, inventory: &Inventory) -> String {
+        match self {
+            GateRequirement::BodyState(SceneModelState::FullBody) => String::from(TEXT_HELP_NO_ARM),
+            GateRequirement::BodyState(_) => String::from(TEXT_HELP_NO_BODY),
+            GateRequirement::Items { item, count } => {
+                let missing = count.saturating_sub(inventory.count(*item));
+                format!(
+                    "I need {} more {} to open \nthis door..",
+                    missing,
+                    item.get_name(missing)
+                )
+            }
+            GateRequirement::Key(key) => format!("This door is locked, \nI need the {}..", key),
+        }
+    }
+}
+
+/// Default requirement of a gate: the one of the first dungeon
+pub fn default_requirements() -> Vec<GateRequirement> {
+    vec![GateRequirement::BodyState(SceneModelState::FullBody)]
+}
 
+/// Component of the gate entity, the one going down while opening
 #[derive(Component)]
-pub struct TagGate;
+pub struct Gate {
+    pub id: u32,
+    pub state: GateState,
+    /// every requirement must be met to open the gate
+    pub requirements: Vec<GateRequirement>,
+}
 
-fn setup_gate_chain(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// Ressource recording the gates already opened in the current level
+/// They are spawned opened when the level is reloaded
+#[derive(Default)]
+pub struct OpenedGates(HashSet<u32>);
+
+/// Spawns every gate and chain of the level
+fn setup_gate_chain(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut opened_gates: ResMut<OpenedGates>,
+) {
+    for LevelReady(handle, load) in level_events.iter() {
+        if *load == LevelLoad::New {
+            opened_gates.0.clear();
+        }
+
+        if let Some(level) = levels.get(handle) {
+            for chain in &level.chains {
+                spawn_chain(commands.borrow_mut(), &asset_server, chain);
+            }
+
+            for gate in &level.gates {
+                let opened = opened_gates.0.contains(&gate.id);
+                spawn_gate(commands.borrow_mut(), &asset_server, gate, opened);
+            }
+        }
+    }
+}
+
+fn spawn_chain(commands: &mut Commands, asset_server: &Res<AssetServer>, chain: &LevelChain) {
     let handle_chain = asset_server.load(PATH_GLTF_CHAIN);
-    let handle_gate = asset_server.load(PATH_GLTF_GATE);
 
-    let position_chain = 6.25 * I_SHIFT + 9.25 * J_SHIFT;
+    let position_chain = grid_to_world(chain.position.0, chain.position.1);
+    // the chain hangs on the wall, Skelly respawns on the tile under it
+    let checkpoint = GridCoord::from_world(position_chain).to_world();
 
     commands
         .spawn_bundle(PbrBundle {
@@ -56,15 +143,37 @@ fn setup_gate_chain(mut commands: Commands, asset_server: Res<AssetServer>) {
                 })
                 .insert(Collider::cone(0.05, 3.0))
                 .insert(Sensor)
-                .insert(TagChain);
+                .insert(
+                    TriggerZone::new(vec![TriggerAction::SetCheckpoint(checkpoint)])
+                        .with_interact(vec![TriggerAction::OpenGate(chain.gate)])
+                        .with_exit(vec![TriggerAction::HideText])
+                        .repeatable()
+                        .with_filter(TriggerFilter::Player),
+                );
         })
-        .insert(Name::new("Chain!"));
+        .insert(TagLevelEntity)
+        .insert(Name::new(format!("Chain {}!", chain.gate)));
+}
 
-    let gate_position = 7.0 * I_SHIFT + 9.65 * J_SHIFT;
+/// An opened gate is spawned under the floor
+fn spawn_gate(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    gate: &LevelGate,
+    opened: bool,
+) {
+    let handle_gate = asset_server.load(PATH_GLTF_GATE);
+
+    let gate_position = grid_to_world(gate.position.0, gate.position.1);
+    let (state, height) = if opened {
+        (GateState::Opened, GATE_OPENED_HEIGHT)
+    } else {
+        (GateState::Closed, 0.0)
+    };
 
     commands
         .spawn_bundle(PbrBundle {
-            transform: Transform::from_xyz(gate_position.x, 0.0, gate_position.z),
+            transform: Transform::from_xyz(gate_position.x, height, gate_position.z),
             ..default()
         })
         .with_children(|parent| {
@@ -88,110 +197,114 @@ fn setup_gate_chain(mut commands: Commands, asset_server: Res<AssetServer>) {
                     },
                     ..default()
                 })
-                .insert(Collider::cuboid(2.5, 2.8, 0.5))
-                .insert(TagGate);
+                .insert(Collider::cuboid(2.5, 2.8, 0.5));
+        })
+        .insert(Gate {
+            id: gate.id,
+            state,
+            requirements: gate.requirements.clone(),
         })
-        .insert(Name::new("Gate!"));
+        .insert(TagLevelEntity)
+        .insert(Name::new(format!("Gate {}!", gate.id)));
 }
 
-fn collision_with_chain_text(
+/// Opens the closed gates of the chain pulled with Interact when every requirement is met,
+/// otherwise the first missing requirement is displayed until Skelly leaves the chain
+fn open_gate_on_trigger(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut collision_events: EventReader<CollisionEvent>,
-    query_chain: Query<Entity, With<TagChain>>,
-    mut query_text: Query<Entity, With<TagUiText>>,
+    mut trigger_events: EventReader<TriggerEvent>,
+    mut query_gate: Query<&mut Gate>,
+    query_inventory: Query<&Inventory, With<Player>>,
     app_state: Res<State<SceneModelState>>,
+    mut opened_gates: ResMut<OpenedGates>,
 ) {
-    if *app_state.current() == SceneModelState::FullBody {
-        return;
-    }
+    let inventory = match query_inventory.get_single() {
+        Ok(inventory) => inventory,
+        Err(_) => return,
+    };
 
-    for event in collision_events.iter() {
-        match event {
-            CollisionEvent::Started(a, b, _) => {
-                for entity in [a, b] {
-                    if let Ok(_chain) = query_chain.get(*entity) {
-                        display_text(
-                            commands.borrow_mut(),
-                            &asset_server,
-                            TEXT_HELP_NO_ARM,
-                            Color::RED,
-                        );
-                    }
-                }
-            }
-            CollisionEvent::Stopped(a, b, _) => {
-                for entity in [a, b] {
-                    if let Ok(_chain) = query_chain.get(*entity) {
-                        if let Ok(text_ui) = query_text.get_single_mut() {
-                            commands.entity(text_ui).despawn_recursive();
-                        }
-                    }
-                }
-            }
-        }
-    }
-}
+    for TriggerEvent(action) in trigger_events.iter() {
+        let gate_id = match action {
+            TriggerAction::OpenGate(gate_id) => *gate_id,
+            _ => continue,
+        };
 
-#[derive(Component)]
-struct TagRemoveGate;
+        for mut gate in query_gate.iter_mut() {
+            if gate.id != gate_id || gate.state != GateState::Closed {
+                continue;
+            }
 
-fn collision_with_chain_door(
-    mut collision_events: EventReader<CollisionEvent>,
-    query_chain: Query<Entity, With<TagChain>>,
-    app_state: Res<State<SceneModelState>>,
-    mut gate_state: ResMut<State<GateState>>,
-) {
-    if !(*app_state.current() == SceneModelState::FullBody
-        && *gate_state.current() == GateState::Closed)
-    {
-        return;
-    }
+            let missing = gate
+                .requirements
+                .iter()
+                .find(|requirement| !requirement.is_met(app_state.current(), inventory))
+                .cloned();
 
-    for event in collision_events.iter() {
-        match event {
-            CollisionEvent::Started(a, b, _) => {
-                for entity in [a, b] {
-                    if let Ok(_chain) = query_chain.get(*entity) {
-                        info!("Opening gate");
-                        gate_state.set(GateState::Opening).unwrap();
-                    }
+            match missing {
+                None => {
+                    info!("Opening gate {}", gate.id);
+                    gate.state = GateState::Opening;
+                    opened_gates.0.insert(gate.id);
+                }
+                Some(requirement) => {
+                    display_text(
+                        commands.borrow_mut(),
+                        &asset_server,
+                        &requirement.rejection_text(inventory),
+                        Color::RED,
+                    );
                 }
             }
-            CollisionEvent::Stopped(_, _, _) => {}
         }
     }
 }
 
-fn tag_gate_to_remove(
-    mut commands: Commands,
-    query_parent: Query<&Parent>,
-    query_gate: Query<Entity, With<TagGate>>,
-) {
-    if let Ok(child_gate) = query_gate.get_single() {
-        if let Ok(gate_entity) = query_parent.get(child_gate) {
-            info!("Tagging gate to remove");
-            commands.entity(gate_entity.get()).insert(TagRemoveGate);
+/// Makes every opening gate go down, until it is under the floor
+fn removing_gate(mut query_gate: Query<(&mut Gate, &mut Transform)>) {
+    for (mut gate, mut gate_transform) in query_gate.iter_mut() {
+        if gate.state != GateState::Opening {
+            continue;
+        }
+
+        if gate_transform.translation.y <= GATE_OPENED_HEIGHT {
+            gate.state = GateState::Opened;
+            info!("Gate {} opened!", gate.id);
+        } else {
+            gate_transform.translation.y -= GATE_OPENING_SPEED;
         }
     }
 }
 
-fn removing_gate(
-    mut query_gate: Query<(Entity, &mut Transform), With<TagRemoveGate>>,
-    mut gate_state: ResMut<State<GateState>>,
-    mut commands: Commands,
-) {
-    if *gate_state.current() == GateState::Closed {
-        return;
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Ok((entity, mut gate_transform)) = query_gate.get_single_mut() {
-        if gate_transform.translation.y <= -3.3 {
-            gate_state.set(GateState::Opened).unwrap();
-            commands.entity(entity).despawn_recursive();
-            info!("Removing Gate!");
-        } else {
-            gate_transform.translation.y -= 0.01;
-        }
+    #[test]
+    fn test_requirements() {
+        let mut inventory = Inventory::default();
+        inventory.add_bone(15);
+
+        let bones = GateRequirement::Items {
+            item: ItemType::Bone,
+            count: 20,
+        };
+        assert!(!bones.is_met(&SceneModelState::FullBody, &inventory));
+        assert_eq!(
+            "I need 5 more bones to open \nthis door..",
+            bones.rejection_text(&inventory)
+        );
+
+        inventory.add_bone(5);
+        assert!(bones.is_met(&SceneModelState::OnlyHead, &inventory));
+
+        let body = GateRequirement::BodyState(SceneModelState::HalfBody);
+        assert!(!body.is_met(&SceneModelState::OnlyHead, &inventory));
+        assert!(body.is_met(&SceneModelState::FullBody, &inventory));
+
+        let key = GateRequirement::Key(String::from("crypt key"));
+        assert!(!key.is_met(&SceneModelState::FullBody, &inventory));
+        inventory.add_key("crypt key");
+        assert!(key.is_met(&SceneModelState::OnlyHead, &inventory));
     }
 }
@@ -0,0 +1,350 @@
+use crate::creatures::{
+    TypeCreature, ARMS_NEEDED_FULL_BODY, BONES_NEEDED_FULL_BODY, CHEST_NEEDED_FULL_BODY,
+    LEGS_NEEDED_FULL_BODY,
+};
+use crate::map::gate::default_requirements;
+use crate::map::grid::GridCoord;
+use crate::map::level::{
+    default_torch_color, default_torch_intensity, FloorRect, Level, LevelChain, LevelCheckpoint,
+    LevelGate, LevelPack, LevelTorch, ProgressRule,
+};
+
+const MIN_ROOMS: i32 = 3;
+const MAX_ROOMS: i32 = 5;
+const MIN_ROOM_SIZE: i32 = 3;
+const MAX_ROOM_SIZE: i32 = 6;
+const MIN_CORRIDOR_LENGTH: i32 = 1;
+const MAX_CORRIDOR_LENGTH: i32 = 4;
+/// The end zone sensor must not reach the gate
+const MIN_GATE_CORRIDOR_LENGTH: i32 = 2;
+const MAX_EXTRA_BONES: i32 = 10;
+const MIN_PACK_SIZE: i32 = 2;
+const MAX_PACK_SIZE: i32 = 10;
+
+/// Reads `--seed <u64>` from the command line
+pub fn seed_from_args() -> Option<u64> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--seed").skip(1);
+    args.next().and_then(|seed| seed.parse().ok())
+}
+
+/// SplitMix64: small, fast and, above all, identical on every platform
+struct SeededRng(u64);
+
+impl SeededRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in [min, max], both included
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        let span = (max - min + 1) as u64;
+        min + (self.next_u64() % span) as i32
+    }
+
+    fn coin_flip(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.range(0, i as i32) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+fn rect(min_i: i32, min_j: i32, max_i: i32, max_j: i32) -> FloorRect {
+    FloorRect {
+        min: GridCoord::new(min_i, min_j),
+        max: GridCoord::new(max_i, max_j),
+    }
+}
+
+/// Builds a new room after `previous`, beyond it along i (or j if `along_j`),
+/// and the straight corridor joining them.
+/// The new room starts inside the span of `previous` on the other axis, so rooms only ever
+/// grow towards +i and +j and never touch a room or corridor other than their neighbours.
+fn next_room(
+    rng: &mut SeededRng,
+    previous: &FloorRect,
+    along_j: bool,
+    corridor_length: i32,
+    size: (i32, i32),
+) -> (FloorRect, FloorRect) {
+    if along_j {
+        let min_i = rng.range(previous.min.i, previous.max.i);
+        let min_j = previous.max.j + corridor_length + 1;
+        let room = rect(min_i, min_j, min_i + size.0 - 1, min_j + size.1 - 1);
+
+        let corridor_i = rng.range(room.min.i, previous.max.i.min(room.max.i));
+        let corridor = rect(corridor_i, previous.max.j + 1, corridor_i, room.min.j - 1);
+        (room, corridor)
+    } else {
+        let min_j = rng.range(previous.min.j, previous.max.j);
+        let min_i = previous.max.i + corridor_length + 1;
+        let room = rect(min_i, min_j, min_i + size.0 - 1, min_j + size.1 - 1);
+
+        let corridor_j = rng.range(room.min.j, previous.max.j.min(room.max.j));
+        let corridor = rect(previous.max.i + 1, corridor_j, room.min.i - 1, corridor_j);
+        (room, corridor)
+    }
+}
+
+/// Center of a room, in grid coordinates
+fn center(room: &FloorRect) -> (f32, f32) {
+    (
+        (room.min.i + room.max.i) as f32 / 2.0,
+        (room.min.j + room.max.j) as f32 / 2.0,
+    )
+}
+
+/// Every item Skelly needs to become FullBody, plus a few random bones
+fn needed_items(rng: &mut SeededRng) -> Vec<TypeCreature> {
+    let extra_bones = rng.range(0, MAX_EXTRA_BONES) as usize;
+
+    let mut items = Vec::new();
+    items.extend(std::iter::repeat(TypeCreature::Bone).take(BONES_NEEDED_FULL_BODY + extra_bones));
+    items.extend(std::iter::repeat(TypeCreature::Chest).take(CHEST_NEEDED_FULL_BODY));
+    items.extend(std::iter::repeat(TypeCreature::Arm).take(ARMS_NEEDED_FULL_BODY));
+    items.extend(std::iter::repeat(TypeCreature::Leg).take(LEGS_NEEDED_FULL_BODY));
+    rng.shuffle(&mut items);
+    items
+}
+
+/// Generates a dungeon: a chain of rooms joined by corridors.
+/// The corridor leading to the last room is closed by the gate, the chain hangs in the room
+/// before it and the end zone fills the last room.
+/// Every pack is placed before the gate and together they hold enough parts to become FullBody,
+/// so the dungeon can always be finished.
+/// The same seed always gives the same dungeon.
+pub fn generate_level(seed: u64) -> Level {
+    let mut rng = SeededRng(seed);
+
+    let nb_rooms = rng.range(MIN_ROOMS, MAX_ROOMS);
+
+    let first_room = rect(
+        0,
+        0,
+        rng.range(MIN_ROOM_SIZE, MAX_ROOM_SIZE) - 1,
+        rng.range(MIN_ROOM_SIZE, MAX_ROOM_SIZE) - 1,
+    );
+    let mut rooms = vec![first_room];
+    let mut corridors = Vec::new();
+
+    // every room but the last one
+    for _ in 1..nb_rooms - 1 {
+        let size = (
+            rng.range(MIN_ROOM_SIZE, MAX_ROOM_SIZE),
+            rng.range(MIN_ROOM_SIZE, MAX_ROOM_SIZE),
+        );
+        let along_j = rng.coin_flip();
+        let corridor_length = rng.range(MIN_CORRIDOR_LENGTH, MAX_CORRIDOR_LENGTH);
+        let (room, corridor) = next_room(
+            &mut rng,
+            rooms.last().unwrap(),
+            along_j,
+            corridor_length,
+            size,
+        );
+        rooms.push(room);
+        corridors.push(corridor);
+    }
+
+    // last room, behind the gate. The gate model only blocks corridors going along j
+    let before_gate = *rooms.last().unwrap();
+    let corridor_length = rng.range(MIN_GATE_CORRIDOR_LENGTH, MAX_CORRIDOR_LENGTH);
+    let (end_room, gate_corridor) = next_room(
+        &mut rng,
+        &before_gate,
+        true,
+        corridor_length,
+        (MIN_ROOM_SIZE, MIN_ROOM_SIZE),
+    );
+
+    // same offsets as the gate and chain of the first dungeon
+    let gate_i = gate_corridor.min.i as f32;
+    let gate = (gate_i, before_gate.max.j as f32 + 0.65);
+    let chain_i = if gate_corridor.min.i > before_gate.min.i {
+        gate_i - 0.75
+    } else {
+        gate_i + 0.75
+    };
+    let chain = (chain_i, before_gate.max.j as f32 + 0.25);
+
+    let player_start = center(&first_room);
+    let start_tile = GridCoord::new(player_start.0.round() as i32, player_start.1.round() as i32);
+    let chain_tile = GridCoord::new(chain.0.round() as i32, chain.1.round() as i32);
+
+    // packs go on room tiles before the gate
+    let mut free_tiles: Vec<GridCoord> = rooms
+        .iter()
+        .flat_map(|room| {
+            (room.min.i..=room.max.i)
+                .flat_map(move |i| (room.min.j..=room.max.j).map(move |j| GridCoord::new(i, j)))
+        })
+        .filter(|tile| *tile != start_tile && *tile != chain_tile)
+        .collect();
+    rng.shuffle(&mut free_tiles);
+
+    let mut packs: Vec<LevelPack> = Vec::new();
+    let mut items = needed_items(&mut rng).into_iter().peekable();
+    let mut tiles = free_tiles.into_iter();
+    while items.peek().is_some() {
+        let pack_size = rng.range(MIN_PACK_SIZE, MAX_PACK_SIZE) as usize;
+        let pack_items: Vec<TypeCreature> = items.by_ref().take(pack_size).collect();
+
+        match tiles.next() {
+            Some(tile) => packs.push(LevelPack {
+                id: packs.len() as u32,
+                position: (tile.i as f32, tile.j as f32),
+                items: pack_items,
+                keys: vec![],
+            }),
+            // no room left: the last pack gets bigger
+            None => packs.last_mut().unwrap().items.extend(pack_items),
+        }
+    }
+
+    corridors.push(gate_corridor);
+    rooms.push(end_room);
+
+    // a checkpoint in every room but the first one, where Skelly starts
+    let checkpoints = rooms
+        .iter()
+        .skip(1)
+        .map(|room| LevelCheckpoint {
+            position: center(room),
+        })
+        .collect();
+
+    // a torch above the center of every room, the default ambient light elsewhere
+    let torches = rooms
+        .iter()
+        .map(|room| LevelTorch {
+            position: center(room),
+            color: default_torch_color(),
+            intensity: default_torch_intensity(),
+        })
+        .collect();
+
+    Level {
+        progress: ProgressRule::CarryOver,
+        floor: rooms.into_iter().chain(corridors).collect(),
+        tiles: vec![],
+        player_start,
+        packs,
+        gates: vec![LevelGate {
+            id: 0,
+            position: gate,
+            requirements: default_requirements(),
+        }],
+        chains: vec![LevelChain {
+            gate: 0,
+            position: chain,
+        }],
+        checkpoints,
+        respawn_cost: None,
+        guards: vec![],
+        torches,
+        ambient: vec![],
+        end_zone: center(&end_room),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::HashSet;
+
+    /// Tiles reachable from `start` walking on the floor, without crossing `blocked`
+    fn reachable(
+        level: &Level,
+        start: GridCoord,
+        blocked: Option<GridCoord>,
+    ) -> HashSet<GridCoord> {
+        let floor: HashSet<GridCoord> = level.floor_tiles().into_iter().collect();
+        let mut visited = HashSet::default();
+        let mut to_visit = vec![start];
+
+        while let Some(tile) = to_visit.pop() {
+            if Some(tile) == blocked || !floor.contains(&tile) || !visited.insert(tile) {
+                continue;
+            }
+            to_visit.extend(tile.neighbours());
+        }
+
+        visited
+    }
+
+    fn to_coord(position: (f32, f32)) -> GridCoord {
+        GridCoord::new(position.0.round() as i32, position.1.round() as i32)
+    }
+
+    #[test]
+    fn test_same_seed_same_dungeon() {
+        assert_eq!(generate_level(42), generate_level(42));
+        assert_ne!(generate_level(42), generate_level(43));
+    }
+
+    #[test]
+    fn test_dungeon_can_be_finished() {
+        for seed in 0..200 {
+            let level = generate_level(seed);
+
+            let start = to_coord(level.player_start);
+            let gate = level.gates[0].position;
+            let gate_tile = GridCoord::new(gate.0 as i32, gate.1.ceil() as i32);
+            let before_gate = reachable(&level, start, Some(gate_tile));
+            let everything = reachable(&level, start, None);
+
+            assert!(
+                before_gate.contains(&to_coord(level.chains[0].position)),
+                "seed {}",
+                seed
+            );
+            assert!(
+                !before_gate.contains(&to_coord(level.end_zone)),
+                "seed {}",
+                seed
+            );
+            assert!(
+                everything.contains(&to_coord(level.end_zone)),
+                "seed {}",
+                seed
+            );
+
+            let items: Vec<TypeCreature> = level
+                .packs
+                .iter()
+                .filter(|pack| before_gate.contains(&to_coord(pack.position)))
+                .flat_map(|pack| pack.items.clone())
+                .collect();
+            let count = |type_creature| items.iter().filter(|item| **item == type_creature).count();
+
+            assert!(
+                count(TypeCreature::Bone) >= BONES_NEEDED_FULL_BODY,
+                "seed {}",
+                seed
+            );
+            assert!(
+                count(TypeCreature::Chest) >= CHEST_NEEDED_FULL_BODY,
+                "seed {}",
+                seed
+            );
+            assert!(
+                count(TypeCreature::Arm) >= ARMS_NEEDED_FULL_BODY,
+                "seed {}",
+                seed
+            );
+            assert!(
+                count(TypeCreature::Leg) >= LEGS_NEEDED_FULL_BODY,
+                "seed {}",
+                seed
+            );
+        }
+    }
+}
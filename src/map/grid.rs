@@ -0,0 +1,139 @@
+use crate::map::{I_SHIFT, J_SHIFT};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// Offsets of the four tiles sharing an edge with a tile
+const ORTHOGONAL_OFFSETS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Coordinates of a tile on the isometric grid
+/// World position is `i * I_SHIFT + j * J_SHIFT`
+/// Written as a tuple `(i, j)` in level files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "(i32, i32)", into = "(i32, i32)")]
+pub struct GridCoord {
+    pub i: i32,
+    pub j: i32,
+}
+
+impl From<(i32, i32)> for GridCoord {
+    fn from((i, j): (i32, i32)) -> Self {
+        Self { i, j }
+    }
+}
+
+impl From<GridCoord> for (i32, i32) {
+    fn from(coord: GridCoord) -> Self {
+        (coord.i, coord.j)
+    }
+}
+
+impl GridCoord {
+    pub const fn new(i: i32, j: i32) -> Self {
+        Self { i, j }
+    }
+
+    /// World position of the center of the tile, at y = 0
+    pub fn to_world(self) -> Vec3 {
+        grid_to_world(self.i as f32, self.j as f32)
+    }
+
+    /// Tile containing the world position (y is ignored)
+    pub fn from_world(position: Vec3) -> Self {
+        let (i, j) = world_to_grid(position);
+        Self::new(i.round() as i32, j.round() as i32)
+    }
+
+    /// The four tiles sharing an edge with this one
+    pub fn neighbours(self) -> impl Iterator<Item = GridCoord> {
+        ORTHOGONAL_OFFSETS
+            .into_iter()
+            .map(move |(di, dj)| GridCoord::new(self.i + di, self.j + dj))
+    }
+}
+
+/// World position of a (possibly fractional) grid position, at y = 0
+pub fn grid_to_world(i: f32, j: f32) -> Vec3 {
+    i * I_SHIFT + j * J_SHIFT
+}
+
+/// Inverse of grid_to_world(): (i, j) of a world position, y is ignored
+pub fn world_to_grid(position: Vec3) -> (f32, f32) {
+    let determinant = I_SHIFT.x * J_SHIFT.z - J_SHIFT.x * I_SHIFT.z;
+    let i = (position.x * J_SHIFT.z - position.z * J_SHIFT.x) / determinant;
+    let j = (position.z * I_SHIFT.x - position.x * I_SHIFT.z) / determinant;
+    (i, j)
+}
+
+/// Ressource recording which tiles hold floor in the current level
+/// Filled by setup_level()
+#[derive(Default, Debug)]
+pub struct TileMap {
+    floor: HashSet<GridCoord>,
+}
+
+impl TileMap {
+    pub fn insert(&mut self, coord: GridCoord) -> bool {
+        self.floor.insert(coord)
+    }
+
+    pub fn clear(&mut self) {
+        self.floor.clear();
+    }
+
+    pub fn is_walkable(&self, coord: GridCoord) -> bool {
+        self.floor.contains(&coord)
+    }
+
+    /// Floor tile under the world position, None if it is above the void
+    pub fn tile_at(&self, position: Vec3) -> Option<GridCoord> {
+        let coord = GridCoord::from_world(position);
+        self.is_walkable(coord).then_some(coord)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &GridCoord> {
+        self.floor.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_round_trip() {
+        let coord = GridCoord::new(7, 15);
+        let position = coord.to_world();
+
+        assert_eq!(position, 7.0 * I_SHIFT + 15.0 * J_SHIFT);
+        assert_eq!(coord, GridCoord::from_world(position));
+        assert_eq!(
+            coord,
+            GridCoord::from_world(position + I_SHIFT * 0.3 - J_SHIFT * 0.3 + Vec3::Y)
+        );
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let coord = GridCoord::new(0, 0);
+
+        assert_eq!(4, coord.neighbours().count());
+        assert!(coord
+            .neighbours()
+            .all(|n| (n.i - coord.i).abs() + (n.j - coord.j).abs() == 1));
+    }
+
+    #[test]
+    fn test_tile_map() {
+        let mut tile_map = TileMap::default();
+        tile_map.insert(GridCoord::new(7, 7));
+
+        assert!(tile_map.is_walkable(GridCoord::new(7, 7)));
+        assert!(!tile_map.is_walkable(GridCoord::new(7, 8)));
+        assert_eq!(
+            Some(GridCoord::new(7, 7)),
+            tile_map.tile_at(grid_to_world(7.2, 6.9))
+        );
+        assert_eq!(None, tile_map.tile_at(grid_to_world(7.0, 8.0)));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::creatures::TypeCreature;
+use crate::inventory::ItemType;
+use crate::map::gate::{default_requirements, GateRequirement};
+use crate::map::grid::GridCoord;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashSet};
+use serde::{Deserialize, Serialize};
+
+/// Levels of the campaign, in the order they are played
+pub static CAMPAIGN_LEVELS: &[&str] = &["levels/dungeon.level.ron", "levels/crypt.level.ron"];
+
+/// What happens to Skelly's body and inventory when a level starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProgressRule {
+    /// Skelly keeps the SceneModelState and Inventory of the previous level
+    CarryOver,
+    /// Skelly starts the level as a head, with an empty Inventory
+    Reset,
+}
+
+impl Default for ProgressRule {
+    fn default() -> Self {
+        Self::CarryOver
+    }
+}
+
+/// Rectangle of floor tiles, `min` and `max` are both included
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct FloorRect {
+    pub min: GridCoord,
+    pub max: GridCoord,
+}
+
+/// A pack of bones, placed at (i, j) and holding `items`
+/// `keys` go straight to the inventory when the pack is opened
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelPack {
+    /// unique in the level, an opened pack stays empty when it is moved
+    pub id: u32,
+    pub position: (f32, f32),
+    pub items: Vec<TypeCreature>,
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+/// A gate, opened by pulling any chain with the same id once every requirement is met
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelGate {
+    pub id: u32,
+    pub position: (f32, f32),
+    #[serde(default = "default_requirements")]
+    pub requirements: Vec<GateRequirement>,
+}
+
+/// A chain, opening the gate with the id `gate`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelChain {
+    pub gate: u32,
+    pub position: (f32, f32),
+}
+
+/// A checkpoint, Skelly respawns on the last one he walked on after falling
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelCheckpoint {
+    pub position: (f32, f32),
+}
+
+/// Items lost by Skelly each time he falls
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RespawnCost {
+    pub item: ItemType,
+    pub count: usize,
+}
+
+/// A guard, patrolling from waypoint to waypoint, starting on the first one
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelGuard {
+    pub waypoints: Vec<(f32, f32)>,
+}
+
+/// A torch, a flickering point light hanging above (i, j)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelTorch {
+    pub position: (f32, f32),
+    /// (r, g, b)
+    #[serde(default = "default_torch_color")]
+    pub color: (f32, f32, f32),
+    /// in lumens
+    #[serde(default = "default_torch_intensity")]
+    pub intensity: f32,
+}
+
+pub fn default_torch_color() -> (f32, f32, f32) {
+    (1.0, 0.6, 0.3)
+}
+
+pub fn default_torch_intensity() -> f32 {
+    400.0
+}
+
+/// Brightness of the ambient light while Skelly is inside `area`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct LevelAmbient {
+    pub area: FloorRect,
+    pub brightness: f32,
+}
+
+/// Description of a level, loaded from a `.level.ron` file by LevelLoader
+/// Every position is expressed in (i, j), multiplied by I_SHIFT and J_SHIFT once spawned
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, TypeUuid)]
+#[uuid = "950e64af-06a8-4c94-9cf9-ccc3b8218f6c"]
+pub struct Level {
+    #[serde(default)]
+    pub progress: ProgressRule,
+    /// rooms and corridors
+    #[serde(default)]
+    pub floor: Vec<FloorRect>,
+    /// single tiles, added on top of `floor`
+    #[serde(default)]
+    pub tiles: Vec<GridCoord>,
+    pub player_start: (f32, f32),
+    #[serde(default)]
+    pub packs: Vec<LevelPack>,
+    #[serde(default)]
+    pub gates: Vec<LevelGate>,
+    #[serde(default)]
+    pub chains: Vec<LevelChain>,
+    #[serde(default)]
+    pub checkpoints: Vec<LevelCheckpoint>,
+    /// None: falling is free
+    #[serde(default)]
+    pub respawn_cost: Option<RespawnCost>,
+    #[serde(default)]
+    pub guards: Vec<LevelGuard>,
+    #[serde(default)]
+    pub torches: Vec<LevelTorch>,
+    /// outside of these areas, the ambient light keeps its default brightness
+    #[serde(default)]
+    pub ambient: Vec<LevelAmbient>,
+    pub end_zone: (f32, f32),
+}
+
+impl Level {
+    /// Returns every floor tile of the level, without duplicates
+    pub fn floor_tiles(&self) -> Vec<GridCoord> {
+        let mut seen = HashSet::default();
+        let mut tiles = Vec::new();
+
+        for rect in &self.floor {
+            for i in rect.min.i..=rect.max.i {
+                for j in rect.min.j..=rect.max.j {
+                    let coord = GridCoord::new(i, j);
+                    if seen.insert(coord) {
+                        tiles.push(coord);
+                    }
+                }
+            }
+        }
+
+        for tile in &self.tiles {
+            if seen.insert(*tile) {
+                tiles.push(*tile);
+            }
+        }
+
+        tiles
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let level = ron::de::from_bytes::<Level>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dungeon_level_parses() {
+        let level: Level =
+            ron::de::from_str(include_str!("../../assets/levels/dungeon.level.ron")).unwrap();
+
+        assert_eq!(11, level.packs.len());
+        assert_eq!(4, level.guards[0].waypoints.len());
+        assert_eq!(ProgressRule::CarryOver, level.progress);
+        assert!(level.floor_tiles().contains(&GridCoord::new(7, 13)));
+        assert_eq!(
+            Some(RespawnCost {
+                item: ItemType::Bone,
+                count: 2
+            }),
+            level.respawn_cost
+        );
+    }
+
+    #[test]
+    fn test_crypt_level_parses() {
+        let level: Level =
+            ron::de::from_str(include_str!("../../assets/levels/crypt.level.ron")).unwrap();
+
+        assert_eq!(ProgressRule::Reset, level.progress);
+        assert_eq!((1.0, 0.6, 0.3), level.torches[0].color);
+    }
+
+    #[test]
+    fn test_floor_tiles_without_duplicates() {
+        let level = Level {
+            progress: ProgressRule::Reset,
+            floor: vec![FloorRect {
+                min: GridCoord::new(0, 0),
+                max: GridCoord::new(1, 1),
+            }],
+            tiles: vec![GridCoord::new(1, 1), GridCoord::new(2, 1)],
+            player_start: (0.0, 0.0),
+            packs: vec![],
+            gates: vec![],
+            chains: vec![],
+            checkpoints: vec![],
+            respawn_cost: None,
+            guards: vec![],
+            torches: vec![],
+            ambient: vec![],
+            end_zone: (0.0, 0.0),
+        };
+
+        assert_eq!(5, level.floor_tiles().len());
+    }
+}
@@ -1,9 +1,21 @@
+use crate::creatures::definition::CreatureDefinitions;
+use crate::creatures::{BoneTag, ToDespawn};
+use crate::ui_text::TagUiText;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::borrow::BorrowMut;
 
+mod checkpoint;
 mod end_zone;
-mod gate;
+pub(crate) mod gate;
+mod generator;
+pub(crate) mod grid;
+pub(crate) mod level;
+pub(crate) mod navigation;
+mod walls;
+
+use grid::{GridCoord, TileMap};
+use level::{Level, LevelLoader, CAMPAIGN_LEVELS};
 
 static PATH_GLTF_FLOOR: &str = "models/floor/floor_material.gltf#Scene0";
 static PATH_GLTF_CHAIN: &str = "models/hanging_wall_chains/scene.gltf#Scene0";
@@ -15,91 +27,204 @@ pub(crate) const J_SHIFT: Vec3 = Vec3::new(2.9, 0.0, 2.8);
 pub struct MapPlugin;
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(gate::GatePlugin)
+        app.add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_event::<LevelReady>()
+            .init_resource::<TileMap>()
+            .add_state(LevelState::Loading)
+            .add_plugin(gate::GatePlugin)
             .add_plugin(end_zone::EndZonePlugin)
-            .add_startup_system(setup_level);
+            .add_plugin(checkpoint::CheckpointPlugin)
+            .add_plugin(navigation::NavigationPlugin)
+            .add_plugin(walls::WallPlugin)
+            .add_startup_system(load_campaign)
+            .add_system(reload_modified_level)
+            .add_system_set(SystemSet::on_update(LevelState::Loading).with_system(setup_level))
+            .add_system_set(SystemSet::on_enter(LevelState::Finished).with_system(unload_level))
+            .add_system_set(SystemSet::on_enter(LevelState::Reloading).with_system(reload_level));
     }
 }
 
-fn setup_level(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let handle_floor = asset_server.load(PATH_GLTF_FLOOR);
-
-    // RIGHT : Vec3::new(-2.8,0.0,2.9)
-    // TOP : Vec3::new(2.9,0.0,2.8)
-    // BOT : Vec3::new(-2.9,0.0,-2.8)
-    // LEFT : Vec3::new(2.8,0.0,-2.9)
-
-    //generate_room_1(commands.borrow_mut(), handle_floor.clone());
-    generate_room_2(commands.borrow_mut(), handle_floor.clone());
-    //generate_corridor_1(commands.borrow_mut(), handle_floor.clone());
-    generate_corridor_2(commands.borrow_mut(), handle_floor.clone());
-    generate_corridor_3(commands.borrow_mut(), handle_floor.clone());
-    generate_room_3(commands.borrow_mut(), handle_floor.clone());
-    generate_room_4(commands.borrow_mut(), handle_floor);
+/// Loading: waiting for the level asset, then spawning it
+/// Playing: the level is spawned
+/// Finished: Skelly reached the end zone, the level is despawned and the next one is loaded
+/// Reloading: the level asset was edited, the level is despawned and spawned again
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LevelState {
+    Loading,
+    Playing,
+    Finished,
+    Reloading,
 }
 
-#[allow(dead_code)]
-fn generate_room_1(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 1..4 {
-        for j in 0..14 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
-        }
+/// Ressource holding the ordered list of levels and the one being played
+pub struct Campaign {
+    levels: Vec<Handle<Level>>,
+    current: usize,
+    /// true while the current level is spawned again after reload_level()
+    reloading: bool,
+}
+
+impl Campaign {
+    pub fn current_level(&self) -> &Handle<Level> {
+        &self.levels[self.current]
+    }
+
+    pub fn has_next_level(&self) -> bool {
+        self.current + 1 < self.levels.len()
     }
 }
 
-#[allow(dead_code)]
-fn generate_corridor_1(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 4..6 {
-        for j in 7..9 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
+/// Marker for every entity belonging to a level, despawned by unload_level()
+#[derive(Component)]
+pub struct TagLevelEntity;
+
+/// Event sent by setup_level() once the floor of a level is spawned
+/// Each plugin reads it to spawn its own part of the level (packs, gate, end zone, player...)
+pub struct LevelReady(pub Handle<Level>, pub LevelLoad);
+
+/// Why a level is spawned
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LevelLoad {
+    /// first time the level is played
+    New,
+    /// the level asset was edited while being played
+    Reload,
+}
+
+/// Loads every level of the campaign,
+/// or generates a dungeon when the game is started with `--seed <u64>`
+fn load_campaign(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut levels: ResMut<Assets<Level>>,
+) {
+    let handle_levels = match generator::seed_from_args() {
+        Some(seed) => {
+            info!("Generating dungeon with seed {}", seed);
+            vec![levels.add(generator::generate_level(seed))]
         }
-    }
+        None => CAMPAIGN_LEVELS
+            .iter()
+            .map(|path| asset_server.load(*path))
+            .collect(),
+    };
+
+    commands.insert_resource(Campaign {
+        levels: handle_levels,
+        current: 0,
+        reloading: false,
+    });
 }
 
-fn generate_room_2(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 6..9 {
-        for j in 3..10 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
+/// Spawns the floor as soon as the current level is loaded, then sends LevelReady
+/// The creatures of the level need their definitions, the level waits for them too
+fn setup_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut campaign: ResMut<Campaign>,
+    levels: Res<Assets<Level>>,
+    definitions: Res<CreatureDefinitions>,
+    mut event_writer: EventWriter<LevelReady>,
+    mut tile_map: ResMut<TileMap>,
+    mut level_state: ResMut<State<LevelState>>,
+) {
+    if !definitions.is_complete() {
+        return;
+    }
+
+    if let Some(level) = levels.get(campaign.current_level()) {
+        let handle_floor = asset_server.load(PATH_GLTF_FLOOR);
+
+        tile_map.clear();
+        for coord in level.floor_tiles() {
+            generate_one_tile(commands.borrow_mut(), handle_floor.clone(), coord);
+            tile_map.insert(coord);
         }
+
+        let load = if campaign.reloading {
+            LevelLoad::Reload
+        } else {
+            LevelLoad::New
+        };
+        campaign.reloading = false;
+
+        info!("Level {} loaded", campaign.current);
+        event_writer.send(LevelReady(campaign.current_level().clone(), load));
+        level_state.set(LevelState::Playing).unwrap();
     }
 }
 
-fn generate_corridor_2(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 9..11 {
-        for j in 7..9 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
-        }
+/// Despawns the finished level and moves the campaign to the next one
+/// Bone parts are tagged ToDespawn so that their stopwatch is removed too
+fn unload_level(
+    mut commands: Commands,
+    mut campaign: ResMut<Campaign>,
+    mut level_state: ResMut<State<LevelState>>,
+    query_level: Query<Entity, With<TagLevelEntity>>,
+    query_parts: Query<Entity, With<BoneTag>>,
+    query_text: Query<Entity, With<TagUiText>>,
+) {
+    info!("Unloading level {}", campaign.current);
+
+    despawn_level(commands.borrow_mut(), &query_level, &query_text);
+
+    for entity in query_parts.iter() {
+        commands.entity(entity).insert(ToDespawn);
     }
+
+    campaign.current += 1;
+    level_state.set(LevelState::Loading).unwrap();
 }
 
-fn generate_room_3(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 11..15 {
-        for j in 3..13 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
+/// Spawns the current level again when its asset changes, on disk or in the editor
+fn reload_modified_level(
+    mut asset_events: EventReader<AssetEvent<Level>>,
+    campaign: Res<Campaign>,
+    mut level_state: ResMut<State<LevelState>>,
+) {
+    let modified = asset_events.iter().any(|event| {
+        matches!(event, AssetEvent::Modified { handle } if handle == campaign.current_level())
+    });
+
+    if modified && *level_state.current() == LevelState::Playing {
+        if let Err(error) = level_state.set(LevelState::Reloading) {
+            warn!("Can not reload the level: {:?}", error);
         }
     }
 }
 
-fn generate_corridor_3(command: &mut Commands, handle_floor: Handle<Scene>) {
-    let i = 7.0;
+/// Despawns the current level to spawn it again from its edited asset
+/// Bone parts lying on the floor are kept, opened packs and gates stay opened
+fn reload_level(
+    mut commands: Commands,
+    mut campaign: ResMut<Campaign>,
+    mut level_state: ResMut<State<LevelState>>,
+    query_level: Query<Entity, With<TagLevelEntity>>,
+    query_text: Query<Entity, With<TagUiText>>,
+) {
+    info!("Reloading level {}", campaign.current);
+
+    despawn_level(commands.borrow_mut(), &query_level, &query_text);
 
-    for j in 10..14 {
-        generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
+    campaign.reloading = true;
+    if let Err(error) = level_state.set(LevelState::Loading) {
+        warn!("Can not load the reloaded level: {:?}", error);
     }
 }
 
-fn generate_room_4(command: &mut Commands, handle_floor: Handle<Scene>) {
-    for i in 6..9 {
-        for j in 14..17 {
-            generate_one_tile(command, handle_floor.clone(), i as f32, j as f32);
-        }
+fn despawn_level(
+    commands: &mut Commands,
+    query_level: &Query<Entity, With<TagLevelEntity>>,
+    query_text: &Query<Entity, With<TagUiText>>,
+) {
+    for entity in query_level.iter().chain(query_text.iter()) {
+        commands.entity(entity).despawn_recursive();
     }
 }
 
-fn generate_one_tile(command: &mut Commands, handle_floor: Handle<Scene>, i: f32, j: f32) {
-    let i_shift = I_SHIFT;
-    let j_shift = J_SHIFT;
-    let transform = (i_shift * i) + (j_shift * j);
+fn generate_one_tile(command: &mut Commands, handle_floor: Handle<Scene>, coord: GridCoord) {
+    let transform = coord.to_world();
 
     let size = 4.35;
     command
@@ -128,5 +253,6 @@ fn generate_one_tile(command: &mut Commands, handle_floor: Handle<Scene>, i: f32
                 })
                 .insert(Collider::cuboid(size / 2.0, 0.1, size / 2.0));
         })
-        .insert(RigidBody::Fixed);
+        .insert(RigidBody::Fixed)
+        .insert(TagLevelEntity);
 }
@@ -0,0 +1,264 @@
+use crate::directions::{Direction, DIRECTIONS};
+use crate::map::gate::{Gate, GateState};
+use crate::map::grid::GridCoord;
+use crate::map::level::Level;
+use crate::map::LevelReady;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Costs of a step, a diagonal step is about sqrt(2) longer
+const COST_ORTHOGONAL: u32 = 10;
+const COST_DIAGONAL: u32 = 14;
+
+pub(crate) struct NavigationPlugin;
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavGrid>()
+            .add_system(build_nav_grid)
+            .add_system(open_gates_in_nav_grid);
+    }
+}
+
+/// (i, j) offset of the tile reached by walking one step along the direction
+/// i goes right and j goes up, like on screen
+pub fn grid_offset(direction: Direction) -> (i32, i32) {
+    match direction {
+        Direction::Up => (0, 1),
+        Direction::UpRight => (1, 1),
+        Direction::Right => (1, 0),
+        Direction::DownRight => (1, -1),
+        Direction::Down => (0, -1),
+        Direction::DownLeft => (-1, -1),
+        Direction::Left => (-1, 0),
+        Direction::UpLeft => (-1, 1),
+    }
+}
+
+fn step(tile: GridCoord, direction: Direction) -> GridCoord {
+    let (di, dj) = grid_offset(direction);
+    GridCoord::new(tile.i + di, tile.j + dj)
+}
+
+/// Ressource answering path queries on the floor of the current level
+/// Closed gates block the edge between the two tiles they stand on
+#[derive(Default)]
+pub struct NavGrid {
+    walkable: HashSet<GridCoord>,
+    closed_gates: HashMap<u32, (GridCoord, GridCoord)>,
+}
+
+impl NavGrid {
+    /// Every gate of the level starts closed
+    pub fn from_level(level: &Level) -> Self {
+        let closed_gates = level
+            .gates
+            .iter()
+            .map(|gate| {
+                // the gate model only blocks corridors going along j
+                let i = gate.position.0.round() as i32;
+                let j = gate.position.1.floor() as i32;
+                (gate.id, (GridCoord::new(i, j), GridCoord::new(i, j + 1)))
+            })
+            .collect();
+
+        Self {
+            walkable: level.floor_tiles().into_iter().collect(),
+            closed_gates,
+        }
+    }
+
+    pub fn open_gate(&mut self, id: u32) {
+        self.closed_gates.remove(&id);
+    }
+
+    pub fn is_gate_closed(&self, id: u32) -> bool {
+        self.closed_gates.contains_key(&id)
+    }
+
+    pub fn is_walkable(&self, tile: GridCoord) -> bool {
+        self.walkable.contains(&tile)
+    }
+
+    /// One orthogonal step between two neighbouring tiles
+    fn can_cross(&self, from: GridCoord, to: GridCoord) -> bool {
+        self.is_walkable(to)
+            && !self
+                .closed_gates
+                .values()
+                .any(|&(a, b)| (a == from && b == to) || (a == to && b == from))
+    }
+
+    /// A diagonal step does not cut corners: both orthogonal ways around must be free
+    pub fn can_step(&self, from: GridCoord, direction: Direction) -> bool {
+        let (di, dj) = grid_offset(direction);
+        let to = step(from, direction);
+        if di == 0 || dj == 0 {
+            return self.can_cross(from, to);
+        }
+
+        let by_i = GridCoord::new(from.i + di, from.j);
+        let by_j = GridCoord::new(from.i, from.j + dj);
+        self.can_cross(from, by_i)
+            && self.can_cross(by_i, to)
+            && self.can_cross(from, by_j)
+            && self.can_cross(by_j, to)
+    }
+
+    /// A* search, returns the tiles to walk on after `start`, up to `goal` included
+    pub fn find_path(&self, start: GridCoord, goal: GridCoord) -> Option<Vec<GridCoord>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<GridCoord, GridCoord> = HashMap::default();
+        let mut costs: HashMap<GridCoord, u32> = HashMap::default();
+
+        costs.insert(start, 0);
+        open.push(Reverse((heuristic(start, goal), start.i, start.j)));
+
+        while let Some(Reverse((_, i, j))) = open.pop() {
+            let tile = GridCoord::new(i, j);
+            if tile == goal {
+                let mut path = Vec::new();
+                let mut current = goal;
+                while current != start {
+                    path.push(current);
+                    current = came_from[&current];
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cost = costs[&tile];
+            for direction in DIRECTIONS {
+                if !self.can_step(tile, direction) {
+                    continue;
+                }
+
+                let next = step(tile, direction);
+                let (di, dj) = grid_offset(direction);
+                let next_cost = cost
+                    + if di == 0 || dj == 0 {
+                        COST_ORTHOGONAL
+                    } else {
+                        COST_DIAGONAL
+                    };
+
+                if costs.get(&next).map_or(true, |known| next_cost < *known) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, tile);
+                    open.push(Reverse((next_cost + heuristic(next, goal), next.i, next.j)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Path between two world positions: the heading and the center of each tile to walk to
+    pub fn find_world_path(&self, from: Vec3, to: Vec3) -> Option<Vec<(Direction, Vec3)>> {
+        let mut previous = GridCoord::from_world(from);
+        let path = self.find_path(previous, GridCoord::from_world(to))?;
+
+        Some(
+            path.into_iter()
+                .map(|tile| {
+                    let direction = DIRECTIONS
+                        .into_iter()
+                        .find(|direction| step(previous, *direction) == tile)
+                        .unwrap();
+                    previous = tile;
+                    (direction, tile.to_world())
+                })
+                .collect(),
+        )
+    }
+
+    /// true if `goal` can be reached from `start` through the gates open right now
+    pub fn is_reachable(&self, start: GridCoord, goal: GridCoord) -> bool {
+        self.find_path(start, goal).is_some()
+    }
+}
+
+/// Octile distance, never more than the real cost
+fn heuristic(from: GridCoord, to: GridCoord) -> u32 {
+    let di = (from.i - to.i).unsigned_abs();
+    let dj = (from.j - to.j).unsigned_abs();
+    COST_ORTHOGONAL * di.max(dj) + (COST_DIAGONAL - COST_ORTHOGONAL) * di.min(dj)
+}
+
+fn build_nav_grid(
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut nav_grid: ResMut<NavGrid>,
+) {
+    for event in level_events.iter() {
+        if let Some(level) = levels.get(&event.0) {
+            *nav_grid = NavGrid::from_level(level);
+        }
+    }
+}
+
+fn open_gates_in_nav_grid(query_gates: Query<&Gate, Changed<Gate>>, mut nav_grid: ResMut<NavGrid>) {
+    for gate in query_gates.iter() {
+        if gate.state == GateState::Opened && nav_grid.is_gate_closed(gate.id) {
+            nav_grid.open_gate(gate.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dungeon() -> NavGrid {
+        let level: Level =
+            ron::de::from_str(include_str!("../../assets/levels/dungeon.level.ron")).unwrap();
+        NavGrid::from_level(&level)
+    }
+
+    #[test]
+    fn test_closed_gate_blocks_the_end_room() {
+        let mut nav_grid = dungeon();
+        let start = GridCoord::new(7, 7);
+        let end_zone = GridCoord::new(7, 15);
+
+        assert!(!nav_grid.is_reachable(start, end_zone));
+        assert!(nav_grid.is_reachable(start, GridCoord::new(14, 12)));
+
+        nav_grid.open_gate(0);
+        let path = nav_grid.find_path(start, end_zone).unwrap();
+        assert_eq!(Some(&end_zone), path.last());
+        assert!(path.contains(&GridCoord::new(7, 10)));
+    }
+
+    #[test]
+    fn test_diagonal_path() {
+        let nav_grid = dungeon();
+
+        // across the starting room, two diagonal steps then straight on
+        let path = nav_grid
+            .find_path(GridCoord::new(6, 3), GridCoord::new(8, 7))
+            .unwrap();
+        assert_eq!(4, path.len());
+
+        let world_path = nav_grid
+            .find_world_path(
+                GridCoord::new(6, 3).to_world(),
+                GridCoord::new(7, 4).to_world(),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![(Direction::UpRight, GridCoord::new(7, 4).to_world())],
+            world_path
+        );
+
+        assert_eq!(
+            Some(vec![]),
+            nav_grid.find_path(GridCoord::new(6, 3), GridCoord::new(6, 3))
+        );
+    }
+}
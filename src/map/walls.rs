@@ -0,0 +1,140 @@
+use crate::map::grid::{GridCoord, TileMap};
+use crate::map::{LevelReady, TagLevelEntity};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+const WALL_HEIGHT: f32 = 3.0;
+const WALL_THICKNESS: f32 = 0.4;
+
+pub struct WallPlugin;
+impl Plugin for WallPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WallSettings>().add_system(setup_walls);
+    }
+}
+
+/// Walls are invisible by default: the camera looks from below the map
+/// and visible walls would hide Skelly
+pub struct WallSettings {
+    pub spawn_meshes: bool,
+    pub color: Color,
+}
+
+impl Default for WallSettings {
+    fn default() -> Self {
+        Self {
+            spawn_meshes: false,
+            color: Color::rgb(0.25, 0.22, 0.2),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct TagWall;
+
+/// One edge of the floor layout: `tile` is a floor tile, `outside` is the empty neighbour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundaryEdge {
+    pub tile: GridCoord,
+    pub outside: GridCoord,
+}
+
+/// Returns every edge between a floor tile and an empty tile
+pub fn boundary_edges(tile_map: &TileMap) -> Vec<BoundaryEdge> {
+    let mut edges = Vec::new();
+
+    for tile in tile_map.iter() {
+        for outside in tile.neighbours() {
+            if !tile_map.is_walkable(outside) {
+                edges.push(BoundaryEdge {
+                    tile: *tile,
+                    outside,
+                });
+            }
+        }
+    }
+
+    edges
+}
+
+/// Spawns a fixed collider (and a mesh if WallSettings.spawn_meshes) on every boundary edge
+fn setup_walls(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelReady>,
+    tile_map: Res<TileMap>,
+    settings: Res<WallSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if level_events.iter().last().is_none() {
+        return;
+    }
+
+    let material = materials.add(settings.color.into());
+
+    for edge in boundary_edges(&tile_map) {
+        let tile_center = edge.tile.to_world();
+        let outward = edge.outside.to_world() - tile_center;
+
+        // the wall lies along the shift vector perpendicular to `outward`
+        let along = Vec3::new(outward.z, 0.0, -outward.x);
+        let half_length = (along.length() + WALL_THICKNESS) / 2.0;
+
+        let position = tile_center
+            + outward / 2.0
+            + outward.normalize() * (WALL_THICKNESS / 2.0)
+            + Vec3::Y * (WALL_HEIGHT / 2.0);
+
+        let mut wall = commands.spawn_bundle(PbrBundle {
+            transform: Transform {
+                translation: position,
+                rotation: Quat::from_rotation_arc(Vec3::X, along.normalize()),
+                scale: Vec3::ONE,
+            },
+            ..default()
+        });
+
+        if settings.spawn_meshes {
+            wall.insert(meshes.add(Mesh::from(shape::Box::new(
+                half_length * 2.0,
+                WALL_HEIGHT,
+                WALL_THICKNESS,
+            ))))
+            .insert(material.clone());
+        }
+
+        wall.insert(RigidBody::Fixed)
+            .insert(Collider::cuboid(
+                half_length,
+                WALL_HEIGHT / 2.0,
+                WALL_THICKNESS / 2.0,
+            ))
+            .insert(TagWall)
+            .insert(TagLevelEntity)
+            .insert(Name::new(format!(
+                "Wall ({}, {}) -> ({}, {})",
+                edge.tile.i, edge.tile.j, edge.outside.i, edge.outside.j
+            )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boundary_edges() {
+        let mut tile_map = TileMap::default();
+        tile_map.insert(GridCoord::new(0, 0));
+        tile_map.insert(GridCoord::new(1, 0));
+
+        let edges = boundary_edges(&tile_map);
+
+        // two tiles side by side: 8 edges minus the 2 shared ones
+        assert_eq!(6, edges.len());
+        assert!(!edges.contains(&BoundaryEdge {
+            tile: GridCoord::new(0, 0),
+            outside: GridCoord::new(1, 0),
+        }));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::creatures::bone_parts::ConsumedPacks;
+use crate::creatures::Player;
+use crate::map::gate::{Gate, GateState};
+use crate::map::grid::{world_to_grid, GridCoord, TileMap};
+use crate::map::level::Level;
+use crate::map::{LevelLoad, LevelReady, TagLevelEntity};
+use crate::trigger_zone::{TriggerAction, TriggerEvent};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+/// Size of one tile on the minimap, in pixels
+const MINIMAP_TILE_SIZE: f32 = 8.0;
+/// Size of the player, packs, gates and end zone, in pixels
+const MINIMAP_MARKER_SIZE: f32 = 5.0;
+const MINIMAP_MARGIN: f32 = 10.0;
+
+const COLOR_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.4);
+const COLOR_FLOOR: Color = Color::rgba(0.55, 0.5, 0.45, 0.9);
+const COLOR_PLAYER: Color = Color::WHITE;
+const COLOR_PACK: Color = Color::rgb(0.9, 0.85, 0.7);
+const COLOR_GATE_CLOSED: Color = Color::RED;
+const COLOR_GATE_OPENED: Color = Color::DARK_GREEN;
+const COLOR_END_ZONE: Color = Color::YELLOW_GREEN;
+
+pub struct MinimapPlugin;
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapFog>()
+            .add_system(setup_minimap)
+            .add_system(reveal_visited_rooms)
+            .add_system(update_minimap_player)
+            .add_system(remove_opened_packs)
+            .add_system(update_minimap_gates);
+    }
+}
+
+/// Ressource recording the rooms of the level and the tiles already seen by Skelly
+/// Every floor rectangle of the level is a room, revealed as a whole when Skelly enters it
+#[derive(Default)]
+pub struct MinimapFog {
+    rooms: Vec<Vec<GridCoord>>,
+    revealed: HashSet<GridCoord>,
+}
+
+impl MinimapFog {
+    fn from_level(level: &Level) -> Self {
+        let mut rooms: Vec<Vec<GridCoord>> = level
+            .floor
+            .iter()
+            .map(|rect| {
+                (rect.min.i..=rect.max.i)
+                    .flat_map(|i| (rect.min.j..=rect.max.j).map(move |j| GridCoord::new(i, j)))
+                    .collect()
+            })
+            .collect();
+        rooms.extend(level.tiles.iter().map(|tile| vec![*tile]));
+
+        Self {
+            rooms,
+            revealed: HashSet::default(),
+        }
+    }
+
+    /// Reveals every room containing the tile, returns true if a new tile was revealed
+    fn visit(&mut self, tile: GridCoord) -> bool {
+        if self.revealed.contains(&tile) {
+            return false;
+        }
+
+        let mut revealed_any = false;
+        for room in self.rooms.iter().filter(|room| room.contains(&tile)) {
+            for coord in room {
+                revealed_any |= self.revealed.insert(*coord);
+            }
+        }
+        revealed_any
+    }
+
+    fn is_revealed(&self, tile: GridCoord) -> bool {
+        self.revealed.contains(&tile)
+    }
+}
+
+/// Root node of the minimap, in the top right corner
+#[derive(Component)]
+struct TagMinimap;
+
+/// Tile or marker, hidden until the room holding this tile is visited
+#[derive(Component)]
+struct MinimapCoord(GridCoord);
+
+#[derive(Component)]
+struct TagMinimapPlayer;
+
+#[derive(Component)]
+struct MinimapPack(u32);
+
+#[derive(Component)]
+struct MinimapGate(u32);
+
+/// Ressource holding the grid coordinates of the bottom left tile of the minimap
+struct MinimapOrigin {
+    min_i: i32,
+    min_j: i32,
+}
+
+impl MinimapOrigin {
+    /// Position of a node of `size` pixels centered on (i, j)
+    /// i goes right and j goes up, like on screen
+    fn position(&self, i: f32, j: f32, size: f32) -> UiRect<Val> {
+        UiRect {
+            left: Val::Px((i - self.min_i as f32 + 0.5) * MINIMAP_TILE_SIZE - size / 2.0),
+            bottom: Val::Px((j - self.min_j as f32 + 0.5) * MINIMAP_TILE_SIZE - size / 2.0),
+            ..default()
+        }
+    }
+}
+
+fn minimap_node(
+    origin: &MinimapOrigin,
+    position: (f32, f32),
+    size: f32,
+    color: Color,
+) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Px(size), Val::Px(size)),
+            position: origin.position(position.0, position.1, size),
+            ..default()
+        },
+        color: UiColor(color),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    }
+}
+
+/// Spawns the minimap of the new level, every tile and marker hidden
+/// A reloaded level keeps the rooms already seen
+fn setup_minimap(
+    mut commands: Commands,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<LevelReady>,
+    mut fog: ResMut<MinimapFog>,
+    consumed_packs: Res<ConsumedPacks>,
+) {
+    let (level, load) = match level_events
+        .iter()
+        .last()
+        .and_then(|LevelReady(handle, load)| levels.get(handle).map(|level| (level, *load)))
+    {
+        Some(level) => level,
+        None => return,
+    };
+
+    let tiles = level.floor_tiles();
+    if tiles.is_empty() {
+        return;
+    }
+
+    let revealed = std::mem::take(&mut fog.revealed);
+    *fog = MinimapFog::from_level(level);
+    if load == LevelLoad::Reload {
+        fog.revealed = revealed;
+    }
+
+    let origin = MinimapOrigin {
+        min_i: tiles.iter().map(|tile| tile.i).min().unwrap(),
+        min_j: tiles.iter().map(|tile| tile.j).min().unwrap(),
+    };
+    let width = (tiles.iter().map(|tile| tile.i).max().unwrap() - origin.min_i + 1) as f32;
+    let height = (tiles.iter().map(|tile| tile.j).max().unwrap() - origin.min_j + 1) as f32;
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(
+                    Val::Px(width * MINIMAP_TILE_SIZE),
+                    Val::Px(height * MINIMAP_TILE_SIZE),
+                ),
+                position: UiRect {
+                    right: Val::Px(MINIMAP_MARGIN),
+                    top: Val::Px(MINIMAP_MARGIN),
+                    ..default()
+                },
+                ..default()
+            },
+            color: UiColor(COLOR_BACKGROUND),
+            ..default()
+        })
+        .with_children(|parent| {
+            for tile in &tiles {
+                parent
+                    .spawn_bundle(minimap_node(
+                        &origin,
+                        (tile.i as f32, tile.j as f32),
+                        MINIMAP_TILE_SIZE,
+                        COLOR_FLOOR,
+                    ))
+                    .insert(MinimapCoord(*tile));
+            }
+
+            for pack in &level.packs {
+                if consumed_packs.contains(pack.id) {
+                    continue;
+                }
+                let coord = to_coord(pack.position);
+                parent
+                    .spawn_bundle(minimap_node(
+                        &origin,
+                        pack.position,
+                        MINIMAP_MARKER_SIZE,
+                        COLOR_PACK,
+                    ))
+                    .insert(MinimapCoord(coord))
+                    .insert(MinimapPack(pack.id));
+            }
+
+            for gate in &level.gates {
+                // the gate sits between two tiles, it is seen from the room of its chain
+                let seen_from = level
+                    .chains
+                    .iter()
+                    .find(|chain| chain.gate == gate.id)
+                    .map_or(gate.position, |chain| chain.position);
+                parent
+                    .spawn_bundle(minimap_node(
+                        &origin,
+                        gate.position,
+                        MINIMAP_MARKER_SIZE,
+                        COLOR_GATE_CLOSED,
+                    ))
+                    .insert(MinimapCoord(to_coord(seen_from)))
+                    .insert(MinimapGate(gate.id));
+            }
+
+            parent
+                .spawn_bundle(minimap_node(
+                    &origin,
+                    level.end_zone,
+                    MINIMAP_MARKER_SIZE,
+                    COLOR_END_ZONE,
+                ))
+                .insert(MinimapCoord(to_coord(level.end_zone)));
+
+            let mut player = minimap_node(
+                &origin,
+                level.player_start,
+                MINIMAP_MARKER_SIZE,
+                COLOR_PLAYER,
+            );
+            player.visibility.is_visible = true;
+            parent.spawn_bundle(player).insert(TagMinimapPlayer);
+        })
+        .insert(TagMinimap)
+        .insert(TagLevelEntity)
+        .insert(Name::new("Minimap"));
+
+    commands.insert_resource(origin);
+}
+
+fn to_coord(position: (f32, f32)) -> GridCoord {
+    GridCoord::new(position.0.round() as i32, position.1.round() as i32)
+}
+
+/// Reveals the room under Skelly, then shows every tile and marker of the revealed rooms
+fn reveal_visited_rooms(
+    mut fog: ResMut<MinimapFog>,
+    tile_map: Res<TileMap>,
+    query_player: Query<&Transform, With<Player>>,
+    mut query_nodes: Query<(&MinimapCoord, &mut Visibility, ChangeTrackers<MinimapCoord>)>,
+) {
+    if let Ok(player_transform) = query_player.get_single() {
+        if let Some(tile) = tile_map.tile_at(player_transform.translation) {
+            // only borrowing mutably when needed, to keep the change detection meaningful
+            if !fog.is_revealed(tile) {
+                fog.visit(tile);
+            }
+        }
+    }
+
+    let fog_changed = fog.is_changed();
+
+    for (coord, mut visibility, tracker) in query_nodes.iter_mut() {
+        if fog_changed || tracker.is_added() {
+            visibility.is_visible = fog.is_revealed(coord.0);
+        }
+    }
+}
+
+fn update_minimap_player(
+    origin: Option<Res<MinimapOrigin>>,
+    query_player: Query<&Transform, (With<Player>, Changed<Transform>)>,
+    mut query_marker: Query<&mut Style, With<TagMinimapPlayer>>,
+) {
+    let origin = match origin {
+        Some(origin) => origin,
+        None => return,
+    };
+
+    if let (Ok(player_transform), Ok(mut style)) =
+        (query_player.get_single(), query_marker.get_single_mut())
+    {
+        let (i, j) = world_to_grid(player_transform.translation);
+        style.position = origin.position(i, j, MINIMAP_MARKER_SIZE);
+    }
+}
+
+/// Removes the marker of the packs opened by Skelly
+fn remove_opened_packs(
+    mut commands: Commands,
+    mut trigger_events: EventReader<TriggerEvent>,
+    query_packs: Query<(Entity, &MinimapPack)>,
+) {
+    for TriggerEvent(action) in trigger_events.iter() {
+        if let TriggerAction::SpawnParts { pack: id, .. } = action {
+            for (entity, pack) in query_packs.iter() {
+                if pack.0 == *id {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+fn update_minimap_gates(
+    query_gates: Query<&Gate, Changed<Gate>>,
+    mut query_markers: Query<(&MinimapGate, &mut UiColor)>,
+) {
+    for gate in query_gates.iter() {
+        for (marker, mut color) in query_markers.iter_mut() {
+            if marker.0 == gate.id {
+                color.0 = match gate.state {
+                    GateState::Opened => COLOR_GATE_OPENED,
+                    GateState::Closed | GateState::Opening => COLOR_GATE_CLOSED,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::level::{FloorRect, ProgressRule};
+
+    #[test]
+    fn test_visit_reveals_whole_room() {
+        let level = Level {
+            progress: ProgressRule::CarryOver,
+            floor: vec![
+                FloorRect {
+                    min: GridCoord::new(0, 0),
+                    max: GridCoord::new(2, 2),
+                },
+                FloorRect {
+                    min: GridCoord::new(3, 1),
+                    max: GridCoord::new(4, 1),
+                },
+            ],
+            tiles: vec![GridCoord::new(5, 1)],
+            player_start: (1.0, 1.0),
+            packs: vec![],
+            gates: vec![],
+            chains: vec![],
+            checkpoints: vec![],
+            respawn_cost: None,
+            guards: vec![],
+            torches: vec![],
+            ambient: vec![],
+            end_zone: (5.0, 1.0),
+        };
+        let mut fog = MinimapFog::from_level(&level);
+
+        assert!(fog.visit(GridCoord::new(1, 1)));
+        assert!(fog.is_revealed(GridCoord::new(2, 2)));
+        assert!(!fog.is_revealed(GridCoord::new(3, 1)));
+
+        // already seen
+        assert!(!fog.visit(GridCoord::new(0, 0)));
+
+        assert!(fog.visit(GridCoord::new(4, 1)));
+        assert!(fog.is_revealed(GridCoord::new(3, 1)));
+        assert!(!fog.is_revealed(GridCoord::new(5, 1)));
+    }
+}
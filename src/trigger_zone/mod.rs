@@ -0,0 +1,259 @@
+use crate::creatures::{Player, TypeCreature};
+use crate::input::{Action, InputActions};
+use crate::map::{LevelLoad, LevelReady};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier3d::prelude::*;
+
+pub struct TriggerZonePlugin;
+impl Plugin for TriggerZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TriggerEvent>()
+            .init_resource::<TriggeredZones>()
+            .add_system(clear_triggered_zones)
+            .add_system(restore_triggered_zones.before(detect_trigger_zones))
+            .add_system(detect_trigger_zones)
+            .add_system(interact_trigger_zones.after(detect_trigger_zones));
+    }
+}
+
+/// What happens when a zone is entered or left
+/// Each action is handled by the plugin owning it (ui_text, gate, end_zone, bone_parts...)
+#[derive(Clone, Debug)]
+pub enum TriggerAction {
+    ShowText {
+        text: String,
+        color: Color,
+    },
+    /// Removes every text displayed by ShowText
+    HideText,
+    /// Opens the gate with this id, if its requirements are met
+    OpenGate(u32),
+    /// Loads the next level of the campaign
+    FinishLevel,
+    /// Spawns the parts around the position
+    SpawnParts {
+        /// id of the pack in the level
+        pack: u32,
+        position: Vec3,
+        items: Vec<TypeCreature>,
+    },
+    /// Puts a key in the player's inventory
+    GiveKey(String),
+    /// Skelly will respawn there after falling
+    SetCheckpoint(Vec3),
+}
+
+/// Who can trigger a zone
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TriggerFilter {
+    Player,
+}
+
+/// Component of a sensor collider, its actions are sent as TriggerEvent
+/// when another collider enters or leaves it, or when the Interact action is pressed inside it
+#[derive(Component, Debug)]
+pub struct TriggerZone {
+    pub on_enter: Vec<TriggerAction>,
+    pub on_exit: Vec<TriggerAction>,
+    /// sent each time Interact is pressed while the zone is occupied
+    pub on_interact: Vec<TriggerAction>,
+    /// a one-shot zone is only entered once
+    pub repeatable: bool,
+    /// None: anything can trigger the zone
+    pub filter: Option<TriggerFilter>,
+    /// identifies a one-shot zone across level reloads
+    pub key: Option<String>,
+    triggered: bool,
+    /// colliders passing the filter currently inside the zone
+    occupants: usize,
+}
+
+impl TriggerZone {
+    /// One-shot zone that anything can trigger
+    pub fn new(on_enter: Vec<TriggerAction>) -> Self {
+        Self {
+            on_enter,
+            on_exit: vec![],
+            on_interact: vec![],
+            repeatable: false,
+            filter: None,
+            key: None,
+            triggered: false,
+            occupants: 0,
+        }
+    }
+
+    pub fn with_exit(mut self, on_exit: Vec<TriggerAction>) -> Self {
+        self.on_exit = on_exit;
+        self
+    }
+
+    pub fn with_interact(mut self, on_interact: Vec<TriggerAction>) -> Self {
+        self.on_interact = on_interact;
+        self
+    }
+
+    pub fn repeatable(mut self) -> Self {
+        self.repeatable = true;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: TriggerFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_key(mut self, key: &str) -> Self {
+        self.key = Some(String::from(key));
+        self
+    }
+
+    /// Marks the zone as entered, returns false if the enter actions must not be sent
+    fn enter(&mut self) -> bool {
+        self.occupants += 1;
+        if self.triggered && !self.repeatable {
+            return false;
+        }
+        self.triggered = true;
+        true
+    }
+}
+
+/// Event sent for every action of a triggered zone
+pub struct TriggerEvent(pub TriggerAction);
+
+/// Ressource recording the keys of the one-shot zones triggered in the current level
+/// They stay triggered when the level is reloaded
+#[derive(Default)]
+pub struct TriggeredZones(HashSet<String>);
+
+fn clear_triggered_zones(
+    mut level_events: EventReader<LevelReady>,
+    mut triggered_zones: ResMut<TriggeredZones>,
+) {
+    for LevelReady(_, load) in level_events.iter() {
+        if *load == LevelLoad::New {
+            triggered_zones.0.clear();
+        }
+    }
+}
+
+/// Marks the zones spawned again by a reload as triggered
+fn restore_triggered_zones(
+    mut query_zone: Query<&mut TriggerZone, Added<TriggerZone>>,
+    triggered_zones: Res<TriggeredZones>,
+) {
+    for mut zone in query_zone.iter_mut() {
+        let triggered = zone
+            .key
+            .as_ref()
+            .map_or(false, |key| triggered_zones.0.contains(key));
+        if triggered {
+            zone.triggered = true;
+        }
+    }
+}
+
+/// Returns true if `entity`, or the creature owning this collider, passes the filter
+fn passes_filter(
+    filter: Option<TriggerFilter>,
+    entity: Entity,
+    parent_query: &Query<&Parent>,
+    query_player: &Query<(), With<Player>>,
+) -> bool {
+    match filter {
+        None => true,
+        Some(TriggerFilter::Player) => {
+            query_player.contains(entity)
+                || parent_query
+                    .get(entity)
+                    .map_or(false, |parent| query_player.contains(parent.get()))
+        }
+    }
+}
+
+/// The only system reading CollisionEvent for sensors:
+/// sends the enter/exit actions of every zone entered or left
+fn detect_trigger_zones(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut query_zone: Query<&mut TriggerZone>,
+    parent_query: Query<&Parent>,
+    query_player: Query<(), With<Player>>,
+    mut event_writer: EventWriter<TriggerEvent>,
+    mut triggered_zones: ResMut<TriggeredZones>,
+) {
+    for collision_event in collision_events.iter() {
+        let (entity_a, entity_b, entering) = match collision_event {
+            CollisionEvent::Started(a, b, _) => (*a, *b, true),
+            CollisionEvent::Stopped(a, b, _) => (*a, *b, false),
+        };
+
+        for (zone_entity, other) in [(entity_a, entity_b), (entity_b, entity_a)] {
+            if let Ok(mut zone) = query_zone.get_mut(zone_entity) {
+                if !passes_filter(zone.filter, other, &parent_query, &query_player) {
+                    continue;
+                }
+
+                let actions = if entering {
+                    if !zone.enter() {
+                        continue;
+                    }
+                    if !zone.repeatable {
+                        if let Some(key) = &zone.key {
+                            triggered_zones.0.insert(key.clone());
+                        }
+                    }
+                    &zone.on_enter
+                } else {
+                    zone.occupants = zone.occupants.saturating_sub(1);
+                    if !zone.triggered {
+                        continue;
+                    }
+                    &zone.on_exit
+                };
+
+                for action in actions {
+                    event_writer.send(TriggerEvent(action.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Sends the interact actions of every occupied zone
+fn interact_trigger_zones(
+    input_actions: Res<InputActions>,
+    query_zone: Query<&TriggerZone>,
+    mut event_writer: EventWriter<TriggerEvent>,
+) {
+    if !input_actions.just_pressed(Action::Interact) {
+        return;
+    }
+
+    for zone in query_zone.iter().filter(|zone| zone.occupants > 0) {
+        for action in &zone.on_interact {
+            event_writer.send(TriggerEvent(action.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_shot_and_repeatable() {
+        let mut one_shot = TriggerZone::new(vec![TriggerAction::FinishLevel]);
+        assert!(!one_shot.triggered);
+        assert!(one_shot.enter());
+        assert!(!one_shot.enter());
+        assert!(one_shot.triggered);
+
+        let mut repeatable = TriggerZone::new(vec![TriggerAction::OpenGate(0)]).repeatable();
+        assert!(repeatable.enter());
+        assert!(repeatable.enter());
+        // a one-shot zone is still occupied after its enter actions
+        assert_eq!(2, one_shot.occupants);
+    }
+}
@@ -1,8 +1,11 @@
 use crate::creatures::SceneModelState;
+use crate::trigger_zone::{TriggerAction, TriggerEvent};
 use bevy::prelude::*;
+use std::borrow::BorrowMut;
 use std::time::Duration;
 
 pub static TEXT_HELP_NO_ARM: &str = "If only I had arms to open \nthis door..";
+pub static TEXT_HELP_NO_BODY: &str = "If only I had a body to open \nthis door..";
 pub static TEXT_NOW_RUN: &str = "Now I can run..";
 
 #[derive(Component)]
@@ -17,7 +20,8 @@ impl Plugin for UiTextPlugin {
         app.add_system_set(
             SystemSet::on_enter(SceneModelState::HalfBody).with_system(display_text_now_run),
         )
-        .add_system(update_or_remove_text_now_run);
+        .add_system(update_or_remove_text_now_run)
+        .add_system(display_text_on_trigger);
     }
 }
 
@@ -35,6 +39,28 @@ fn update_or_remove_text_now_run(
     }
 }
 
+/// Handles the ShowText and HideText actions of the trigger zones
+fn display_text_on_trigger(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut trigger_events: EventReader<TriggerEvent>,
+    query_text: Query<Entity, With<TagUiText>>,
+) {
+    for TriggerEvent(action) in trigger_events.iter() {
+        match action {
+            TriggerAction::ShowText { text, color } => {
+                display_text(commands.borrow_mut(), &asset_server, text, *color);
+            }
+            TriggerAction::HideText => {
+                for entity in query_text.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 fn display_text_now_run(mut commands: Commands, asset_server: Res<AssetServer>) {
     let handle_font = asset_server.load("fonts/FiraMono-Medium.ttf");
 